@@ -0,0 +1,48 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use thermite::bytecode;
+use thermite::vm::WordSize;
+use runner;
+
+pub struct Assembler {
+	input: String,
+	output: String,
+	word_size: WordSize,
+}
+
+impl Assembler {
+	pub fn new(input: String, output: Option<String>, word_size: WordSize) -> Assembler {
+		let output = output.unwrap_or_else(|| Path::new(&input).with_extension("tbc").to_string_lossy().into_owned());
+		Assembler {
+			input,
+			output,
+			word_size
+		}
+	}
+
+	pub fn run(&mut self) {
+		let mut contents = String::new();
+		if let Err(error) = File::open(&self.input).and_then(|mut input| input.read_to_string(&mut contents)) {
+			println!("{}: {}", self.input, error);
+			process::exit(1);
+		}
+		let program = runner::from_source(&self.input, contents);
+		if runner::check(&self.input, &program) {
+			process::exit(1);
+		}
+
+		let bytes = match bytecode::assemble(&program.instructions, self.word_size) {
+			Ok(bytes) => bytes,
+			Err(error) => {
+				println!("{}: {}", self.input, error);
+				process::exit(1);
+			}
+		};
+		if let Err(error) = File::create(&self.output).and_then(|mut output| output.write_all(&bytes)) {
+			println!("{}: {}", self.output, error);
+			process::exit(1);
+		}
+	}
+}
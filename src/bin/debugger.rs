@@ -0,0 +1,267 @@
+use std::io::{stdin, stdout};
+use std::io::prelude::*;
+use std::process;
+use thermite::vm::{VM, Config, StepResult, VMError};
+use thermite::instructions::{Instruction, BranchType, ControlType};
+use thermite::registers::{Register, FloatRegister};
+use thermite::lexer::{self, Token};
+use runner;
+
+const PROMPT: &str = "(tdb) ";
+
+const HELP: &str = "Commands:
+step [n]                Execute the next n instructions (default 1).
+next                    Like step, but runs a call until it returns.
+continue                Run until a breakpoint, a watched register changes or the program stops.
+break <label|line>      Stop before a label or the first instruction on a source line.
+                        With no argument, lists the breakpoints.
+watch <register>        Stop whenever a register changes.
+regs                    Show the registers.
+disas [n]               Show the n instructions either side of the current one (default 3).
+set <register> <value>  Change a register.
+quit                    Exit the debugger.
+Commands may be shortened to their first letter, and an empty line repeats the last one.
+";
+
+/// Why execution stopped.
+enum Stop {
+	Step,
+	Breakpoint(usize),
+	Watch(Register, i64, i64),
+	Halted,
+	Error(VMError),
+}
+
+pub struct Debugger {
+	filename: String,
+	vm: VM,
+	/// The source line of each instruction, when debugging source rather than
+	/// an object file.
+	lines: Option<Vec<u32>>,
+	breakpoints: Vec<usize>,
+	watches: Vec<Register>,
+}
+
+impl Debugger {
+	pub fn new(filename: String) -> Debugger {
+		let mut config = Config::default();
+		let program = runner::load(&filename);
+		if runner::check(&filename, &program) {
+			process::exit(1);
+		}
+		if let Some(word_size) = program.word_size {
+			config.word_size = word_size;
+		}
+		let lines = program.source.map(|(_, spans)| spans.iter().map(|span| span.line).collect());
+		let mut vm = VM::with_config(config);
+		if let Err(error) = vm.load(program.instructions) {
+			println!("VMError: {}", error);
+			process::exit(1);
+		}
+		Debugger {
+			filename,
+			vm,
+			lines,
+			breakpoints: Vec::new(),
+			watches: Vec::new(),
+		}
+	}
+
+	pub fn run(&mut self) {
+		println!("Debugging {}. Type `help` for a list of commands.", self.filename);
+		self.show_current();
+		let stdin = stdin();
+		let mut last = String::new();
+		loop {
+			print!("{}", PROMPT);
+			stdout().flush().ok();
+			let mut input = String::new();
+			match stdin.read_line(&mut input) {
+				Ok(0) | Err(_) => break,
+				Ok(_) => {},
+			}
+			let input = match input.trim() {
+				"" => last.clone(),
+				command => command.to_string(),
+			};
+			let words = input.split_whitespace().collect::<Vec<_>>();
+			match words.first().cloned() {
+				Some("quit") | Some("q") => break,
+				Some(command) => self.command(command, &words[1..]),
+				None => {},
+			}
+			last = input;
+		}
+	}
+
+	fn command(&mut self, command: &str, arguments: &[&str]) {
+		match (command, arguments) {
+			("step", _) | ("s", _) => {
+				let count = match arguments.first().map(|count| count.parse()) {
+					Some(Ok(count)) => count,
+					None => 1,
+					Some(Err(_)) => return println!("Expected a number of instructions to step"),
+				};
+				for _ in 0..count {
+					match self.resume(|_| true) {
+						Some(Stop::Step) => {},
+						stop => return self.report(stop),
+					}
+				}
+				self.report(Some(Stop::Step));
+			},
+			("next", []) | ("n", []) => {
+				let ip = self.vm.ip();
+				let stop = match self.vm.program().get(ip) {
+					Some(&Instruction::Branch(BranchType::CALL, _)) => {
+						let depth = self.vm.stack().len();
+						self.resume(|vm| vm.ip() == ip + 1 && vm.stack().len() == depth)
+					},
+					_ => self.resume(|_| true),
+				};
+				self.report(stop);
+			},
+			("continue", []) | ("c", []) => {
+				let stop = self.resume(|_| false);
+				self.report(stop);
+			},
+			("break", []) | ("b", []) => {
+				for (number, &index) in self.breakpoints.iter().enumerate() {
+					println!("Breakpoint {} at {}", number + 1, self.location(index));
+				}
+			},
+			("break", [target]) | ("b", [target]) => {
+				let index = match target.parse::<u32>() {
+					Ok(line) => match self.lines {
+						Some(ref lines) => lines.iter().position(|&l| l >= line),
+						None => return println!("Line numbers aren't available for object files"),
+					},
+					Err(_) => self.vm.label(target),
+				};
+				// Jumps continue after a label rather than executing it, so stop
+				// at the first instruction that follows instead.
+				let index = index.map(|index| index + self.vm.program()[index..].iter().take_while(|i| matches!(**i, Instruction::Control(ControlType::LBL(_)))).count())
+					.filter(|&index| index < self.vm.program().len());
+				match index {
+					Some(index) => {
+						self.breakpoints.push(index);
+						println!("Breakpoint {} at {}", self.breakpoints.len(), self.location(index));
+					},
+					None => println!("No instruction at `{}`", target),
+				}
+			},
+			("watch", [name]) | ("w", [name]) => {
+				match self.register(name) {
+					Some(register) => {
+						self.watches.push(register);
+						println!("Watching {} = {}", register, self.vm.registers()[register.index()]);
+					},
+					None => println!("Unknown register `{}`", name),
+				}
+			},
+			("regs", []) | ("r", []) => {
+				let registers = self.vm.registers().iter().enumerate()
+					.map(|(index, value)| format!("{:<4} {:>20}", Register::from_index(index as u8).unwrap(), value))
+					.collect::<Vec<_>>();
+				for row in registers.chunks(3) {
+					println!("{}", row.join("    "));
+				}
+				let floats = self.vm.float_registers().iter().enumerate()
+					.map(|(index, value)| format!("{:<4} {:>20?}", FloatRegister::from_index(index as u8).unwrap(), value))
+					.collect::<Vec<_>>();
+				for row in floats.chunks(3) {
+					println!("{}", row.join("    "));
+				}
+			},
+			("disas", _) | ("d", _) => {
+				let context = match arguments.first().map(|count| count.parse()) {
+					Some(Ok(count)) => count,
+					None => 3,
+					Some(Err(_)) => return println!("Expected a number of instructions"),
+				};
+				let ip = self.vm.ip();
+				let start = ip.saturating_sub(context);
+				for (index, instruction) in self.vm.program().iter().enumerate().skip(start).take(ip - start + context + 1) {
+					let marker = if index == ip && self.vm.is_running() { "=>" } else if self.breakpoints.contains(&index) { "*" } else { "" };
+					println!("{:>2} {:04}  {}", marker, index, instruction);
+				}
+			},
+			("set", [name, value]) => {
+				let register = match self.register(name) {
+					Some(register) => register,
+					None => return println!("Unknown register `{}`", name),
+				};
+				let tokens = lexer::tokenize(value).map(|tokens| tokens.into_iter().map(|t| t.node).collect::<Vec<_>>());
+				match tokens.as_ref().map(|tokens| tokens.as_slice()) {
+					Ok([Token::Value(value)]) => {
+						self.vm.set_register(register, *value).unwrap();
+						println!("{} = {}", register, self.vm.registers()[register.index()]);
+					},
+					_ => println!("Expected a value, found `{}`", value),
+				}
+			},
+			("help", _) | ("h", _) => print!("{}", HELP),
+			_ => println!("Unrecognised command `{}`, type `help` for a list of commands", command),
+		}
+	}
+
+	/// Executes instructions until `done` holds after one of them, a
+	/// breakpoint or a watched register change is reached, or the program
+	/// stops. Returns `None` if the program has already stopped.
+	fn resume<F: Fn(&VM) -> bool>(&mut self, done: F) -> Option<Stop> {
+		if !self.vm.is_running() {
+			return None;
+		}
+		let watched = self.watches.iter().map(|register| self.vm.registers()[register.index()]).collect::<Vec<_>>();
+		loop {
+			match self.vm.step() {
+				Ok(StepResult::Running) => {},
+				Ok(StepResult::Halted) => return Some(Stop::Halted),
+				Err(error) => return Some(Stop::Error(error)),
+			}
+			for (&register, &old) in self.watches.iter().zip(watched.iter()) {
+				let new = self.vm.registers()[register.index()];
+				if new != old {
+					return Some(Stop::Watch(register, old, new));
+				}
+			}
+			if done(&self.vm) {
+				return Some(Stop::Step);
+			}
+			if let Some(number) = self.breakpoints.iter().position(|&index| index == self.vm.ip()) {
+				return Some(Stop::Breakpoint(number));
+			}
+		}
+	}
+
+	fn report(&self, stop: Option<Stop>) {
+		match stop {
+			None => return println!("The program is not running"),
+			Some(Stop::Step) => {},
+			Some(Stop::Breakpoint(number)) => println!("Breakpoint {} at {}", number + 1, self.location(self.vm.ip())),
+			Some(Stop::Watch(register, old, new)) => println!("{} changed from {} to {}", register, old, new),
+			Some(Stop::Halted) => return println!("The program halted"),
+			Some(Stop::Error(error)) => return println!("VMError: {}", error),
+		}
+		self.show_current();
+	}
+
+	fn show_current(&self) {
+		let ip = self.vm.ip();
+		if let Some(instruction) = self.vm.program().get(ip) {
+			println!("=> {:<24} {}", instruction.to_string(), self.location(ip));
+		}
+	}
+
+	/// Describes where an instruction is, by source line when known.
+	fn location(&self, index: usize) -> String {
+		match self.lines.as_ref().and_then(|lines| lines.get(index)) {
+			Some(line) => format!("{}:{}", self.filename, line),
+			None => format!("{:04}", index),
+		}
+	}
+
+	fn register(&self, name: &str) -> Option<Register> {
+		Register::from_name(name).filter(|register| register.index() < self.vm.registers().len())
+	}
+}
@@ -0,0 +1,34 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+use thermite::bytecode::Object;
+use thermite::disassembler::{self, Options};
+
+pub struct Disassembler {
+	filename: String,
+	options: Options,
+}
+
+impl Disassembler {
+	pub fn new(filename: String, options: Options) -> Disassembler {
+		Disassembler {
+			filename,
+			options
+		}
+	}
+
+	pub fn run(&mut self) {
+		let mut bytes = vec![];
+		if let Err(error) = File::open(&self.filename).and_then(|mut input| input.read_to_end(&mut bytes)) {
+			println!("{}: {}", self.filename, error);
+			process::exit(1);
+		}
+		match Object::from_bytes(&bytes).and_then(|object| disassembler::disassemble(&object, self.options)) {
+			Ok(text) => print!("{}", text),
+			Err(error) => {
+				println!("{}: {}", self.filename, error);
+				process::exit(1);
+			}
+		}
+	}
+}
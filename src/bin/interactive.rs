@@ -1,45 +1,169 @@
 use std::io::{stdin, stdout};
 use std::io::{Stdin, Stdout};
 use std::io::prelude::*;
-use thermite::vm::VM;
+use thermite::vm::{VM, Config, StepResult, VMErrorKind};
+use thermite::instructions::{Instruction, ControlType};
+use thermite::registers::Register;
 use thermite::lexer;
-use thermite::parser;
+use thermite::parser::{self, Aliases};
 
-const WELCOME: &'static str = "Welcome to the \x1b[1mthermite\x1b[0m interactive mode.\nUse \x1b[1mctrl-c\x1b[0m to exit.\n";
+const WELCOME: &str = "Welcome to the \x1b[1mthermite\x1b[0m interactive mode.\nType \x1b[1m:help\x1b[0m for help, or use \x1b[1mctrl-d\x1b[0m to exit.\n";
 
-const PROMPT: &'static str = "\x1B[36mvm> \x1B[37m";
+const PROMPT: &str = "\x1B[36mvm> \x1B[37m";
+
+const CONTINUATION: &str = "\x1B[36m... \x1B[37m";
+
+/// The most instructions a single entered block may run, so a block that
+/// never finishes hands control back to the prompt.
+const FUEL: u64 = 10_000_000;
+
+const HELP: &str = "Instructions run as soon as they are entered, and branches can jump back to
+labels entered earlier. A line ending in a label, such as `loop:`, starts a
+block, which runs when an empty line is entered. A block that runs more than
+10,000,000 instructions is stopped.
+Commands:
+:history  Show what has been entered so far.
+:regs     Show the registers.
+:help     Show this message.
+:quit     Exit.
+";
 
 pub struct Interactive {
 	vm: VM,
 	stdin: Stdin,
 	stdout: Stdout,
+	/// Every block that has been loaded, in order.
+	history: Vec<String>,
+	/// Register names defined with `.alias` by earlier blocks.
+	aliases: Aliases,
 }
 
 impl Interactive {
 	pub fn new() -> Interactive {
+		let mut vm = VM::with_config(Config { fuel: Some(FUEL), ..Config::default() });
+		// An empty program allocates the registers, so `:regs` works straight away.
+		vm.extend(Vec::new()).expect("the default config is valid");
 		Interactive {
-			vm:  VM::new(),
+			vm,
 			stdin: stdin(),
-			stdout: stdout()
+			stdout: stdout(),
+			history: Vec::new(),
+			aliases: Aliases::default(),
 		}
 	}
 	pub fn run(&mut self) {
 		self.stdout.write_all(WELCOME.as_bytes()).unwrap();
 		self.stdout.flush().ok();
 
-		loop {
-			self.stdout.write_all(PROMPT.as_bytes()).unwrap();
-			self.stdout.flush().ok();
-			let mut input = String::new();
-			self.stdin.read_line(&mut input).unwrap();
-			if input.trim() == "" {continue}
-			let tokens = lexer::tokenize(input.as_ref());
-
-			let program = parser::parse(tokens);
-			match program.len() {
-				1 => self.vm.interactive(program[0].clone()),
-				_ => println!("Interactive Mode: Only one instruction allowed by line")
+		while let Some(input) = self.read_block() {
+			match input.trim() {
+				"" => {},
+				":quit" | ":q" => break,
+				":help" | ":h" => print!("{}", HELP),
+				":history" => {
+					for (number, block) in self.history.iter().enumerate() {
+						for (offset, line) in block.lines().enumerate() {
+							match offset {
+								0 => println!("{:>3}  {}", number + 1, line),
+								_ => println!("     {}", line),
+							}
+						}
+					}
+				},
+				":regs" | ":r" => {
+					let registers = self.vm.registers().iter().enumerate()
+						.map(|(index, value)| format!("{} = {}", Register::from_index(index as u8).unwrap(), value))
+						.collect::<Vec<_>>();
+					println!("{}", registers.join(", "));
+				},
+				command if command.starts_with(':') => println!("Unrecognised command `{}`, type `:help` for a list of commands", command),
+				_ => self.execute(input),
+			}
+		}
+		println!();
+	}
+
+	/// Reads a line, or a whole block if the line ends in a label. Returns
+	/// `None` at the end of input.
+	fn read_block(&mut self) -> Option<String> {
+		let mut block = self.read_line(PROMPT)?;
+		if ends_in_label(&block, &self.aliases) {
+			loop {
+				match self.read_line(CONTINUATION) {
+					Some(ref line) if !line.trim().is_empty() => block.push_str(line),
+					_ => break,
+				}
+			}
+		}
+		Some(block)
+	}
+
+	fn read_line(&mut self, prompt: &str) -> Option<String> {
+		self.stdout.write_all(prompt.as_bytes()).unwrap();
+		self.stdout.flush().ok();
+		let mut line = String::new();
+		match self.stdin.read_line(&mut line) {
+			Ok(0) | Err(_) => None,
+			Ok(_) => Some(line),
+		}
+	}
+
+	/// Adds a block to the program and runs it until it halts, fails or
+	/// reaches the end of what has been entered, or runs out of fuel.
+	fn execute(&mut self, input: String) {
+		// Aliases only carry over once the block is part of the program.
+		let mut aliases = self.aliases.clone();
+		let program = match lexer::tokenize(input.as_ref()).and_then(|tokens| parser::parse_with(tokens, &mut aliases)) {
+			Ok(program) => program,
+			Err(diagnostics) => {
+				for diagnostic in diagnostics {
+					print!("{}", diagnostic.render("<repl>", &input));
+				}
+				return
 			}
+		};
+		if let Err(error) = self.vm.extend(program) {
+			return println!("VMError: {}", error);
 		}
+		self.aliases = aliases;
+		self.history.push(input.trim_end().to_string());
+		while self.vm.ip() < self.vm.program().len() {
+			match self.vm.step() {
+				Ok(StepResult::Running) => {},
+				Ok(StepResult::Halted) => break,
+				Err(ref error) if error.kind == VMErrorKind::OutOfFuel => {
+					return println!("Stopped after {} instructions at ip {}, the block may never finish", FUEL, error.ip)
+				},
+				Err(error) => return println!("VMError: {}", error),
+			}
+		}
+	}
+}
+
+/// Whether the last instruction on a line is a label, ignoring lines that
+/// don't parse.
+fn ends_in_label(line: &str, aliases: &Aliases) -> bool {
+	match lexer::tokenize(line).and_then(|tokens| parser::parse_with(tokens, &mut aliases.clone())) {
+		Ok(program) => matches!(program.last(), Some(&Instruction::Control(ControlType::LBL(_)))),
+		Err(_) => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[test]
+	fn aliases_carry_over() {
+		let mut repl = Interactive::new();
+		repl.execute(".alias counter ra\n".to_string());
+		repl.execute("str 5 counter\n".to_string());
+		repl.execute("loop:\nadd counter 1 counter\n".to_string());
+		assert_eq!(repl.vm.registers()[Register::RA.index()], 6);
+		assert_eq!(repl.history.len(), 3);
+		// A block that doesn't load defines nothing.
+		repl.execute(".alias total rb\njmp nowhere\n".to_string());
+		repl.execute("str 1 total\n".to_string());
+		assert_eq!(repl.history.len(), 3);
+		assert!(ends_in_label("add counter 1 counter\nnext:\n", &repl.aliases));
 	}
-}
\ No newline at end of file
+}
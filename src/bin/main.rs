@@ -2,21 +2,40 @@
 extern crate thermite;
 
 use std::env;
+use std::process;
 
 mod interactive;
 mod runner;
+mod assembler;
+mod disassembler;
+mod debugger;
 use runner::Runner;
 use interactive::Interactive;
+use assembler::Assembler;
+use disassembler::Disassembler;
+use debugger::Debugger;
+use thermite::vm::{Config, Overflow, WordSize};
+use thermite::registers::MAX_REGISTERS;
 
 
-static USAGE: &'static str = "
+static USAGE: &str = "
 Usage:
 thermite repl
-thermite run
+thermite run <file> [--overflow <mode>] [--registers <count>] [--word-size <bits>] [--max-steps <count>]
+thermite check <file>
+thermite debug <file>
+thermite asm <file> [-o <output>] [--word-size <bits>]
+thermite disasm <file> [--hex]
 thermite -h | --help
 thermite --version
 Options:
 -h, --help      Show this message.
+--hex       Show the encoded bytes of each instruction.
+--overflow <mode>  Arithmetic overflow behaviour: wrapping (default), checked or saturating.
+--registers <count>  Number of registers, r0 to r254 (default 6).
+--word-size <bits>  Integer register width, 32 (default) or 64. Object files
+                    keep the width they were assembled with.
+--max-steps <count>  Stop with an error after executing this many instructions.
 --version   Display the version.
 ";
 
@@ -34,12 +53,107 @@ fn main() {
 				"run" => {
 					match arguments.next() {
 						Some(filename) => {
-							let mut runner = Runner::new(filename);
+							let mut config = Config::default();
+							while let Some(flag) = arguments.next() {
+								match flag.as_ref() {
+									"--overflow" => match arguments.next().as_ref().and_then(|mode| Overflow::from_name(mode)) {
+										Some(overflow) => config.overflow = overflow,
+										None => {
+											println!("Expected wrapping, checked or saturating after --overflow\n{}", USAGE);
+											return;
+										}
+									},
+									"--registers" => match arguments.next().and_then(|count| count.parse().ok()) {
+										Some(count) if count <= MAX_REGISTERS => config.registers = count,
+										_ => {
+											println!("Expected a register count up to {} after --registers\n{}", MAX_REGISTERS, USAGE);
+											return;
+										}
+									},
+									"--max-steps" => match arguments.next().and_then(|count| count.parse().ok()) {
+										Some(count) => config.fuel = Some(count),
+										None => {
+											println!("Expected a number of instructions after --max-steps\n{}", USAGE);
+											return;
+										}
+									},
+									"--word-size" => match arguments.next().and_then(|bits| bits.parse().ok()).and_then(WordSize::from_bits) {
+										Some(word_size) => config.word_size = word_size,
+										None => {
+											println!("Expected 32 or 64 after --word-size\n{}", USAGE);
+											return;
+										}
+									},
+									_ => {
+										println!("Unrecognised option `{}`\n{}", flag, USAGE);
+										return;
+									}
+								}
+							}
+							let mut runner = Runner::new(filename, config);
 							runner.run();
 						}
 						_ => println!("No file specified\n{}", USAGE),
 					}
 				}
+				"check" => {
+					match arguments.next() {
+						Some(filename) => {
+							let program = runner::load(&filename);
+							if runner::check(&filename, &program) {
+								process::exit(1);
+							}
+						}
+						_ => println!("No file specified\n{}", USAGE),
+					}
+				}
+				"debug" => {
+					match arguments.next() {
+						Some(filename) => {
+							let mut debugger = Debugger::new(filename);
+							debugger.run();
+						}
+						_ => println!("No file specified\n{}", USAGE),
+					}
+				}
+				"asm" => {
+					match arguments.next() {
+						Some(filename) => {
+							let (mut output, mut word_size) = (None, WordSize::Bits32);
+							while let Some(flag) = arguments.next() {
+								match flag.as_ref() {
+									"-o" => output = arguments.next(),
+									"--word-size" => match arguments.next().and_then(|bits| bits.parse().ok()).and_then(WordSize::from_bits) {
+										Some(bits) => word_size = bits,
+										None => {
+											println!("Expected 32 or 64 after --word-size\n{}", USAGE);
+											return;
+										}
+									},
+									_ => {
+										println!("Unrecognised option `{}`\n{}", flag, USAGE);
+										return;
+									}
+								}
+							}
+							let mut assembler = Assembler::new(filename, output, word_size);
+							assembler.run();
+						}
+						_ => println!("No file specified\n{}", USAGE),
+					}
+				}
+				"disasm" => {
+					match arguments.next() {
+						Some(filename) => {
+							let options = thermite::disassembler::Options {
+								hex: arguments.any(|argument| argument == "--hex"),
+							};
+							let mut disassembler = Disassembler::new(filename, options);
+							disassembler.run();
+						}
+						_ => println!("No file specified\n{}", USAGE),
+					}
+				}
 				_ => println!("Unrecognised command \n{}", USAGE),
 			}
 		},
@@ -1,35 +1,125 @@
 use std::fs::File;
 use std::io::prelude::*;
-use thermite::vm::VM;
+use std::process;
+use thermite::vm::{VM, Config, WordSize};
+use thermite::instructions::Instruction;
+use thermite::diagnostic::{Diagnostic, Severity, Span, Spanned};
 use thermite::lexer;
 use thermite::parser;
+use thermite::bytecode;
+use thermite::analysis::{self, Issue};
 
 pub struct Runner {
-	filename: String
+	filename: String,
+	config: Config,
 }
 
-impl Runner {
-	pub fn new(filename: String) -> Runner {
-		Runner {
-			filename: filename
+/// A program read by `load`.
+pub struct Program {
+	pub instructions: Vec<Instruction>,
+	/// The word size an object file was assembled for.
+	pub word_size: Option<WordSize>,
+	/// The source text and the span of each instruction, when loaded from
+	/// source rather than an object file.
+	pub source: Option<(String, Vec<Span>)>,
+}
+
+impl Program {
+	/// Prints a problem found by static analysis to stderr, pointing at the
+	/// offending instruction's source if known.
+	pub fn report(&self, filename: &str, issue: &Issue) {
+		let severity = if issue.kind.is_error() { Severity::Error } else { Severity::Warning };
+		let message = issue.kind.to_string();
+		match (issue.position, self.source.as_ref()) {
+			(Some(index), Some((source, spans))) => {
+				let diagnostic = Diagnostic { severity, message, span: spans[index] };
+				eprint!("{}", diagnostic.render(filename, source));
+			},
+			(Some(index), None) => eprintln!("{}: {}\n --> {}, instruction {} ({})", severity, message, filename, index, self.instructions[index]),
+			(None, _) => eprintln!("{}: {}\n --> {}", severity, message, filename),
 		}
 	}
+}
 
-	pub fn run(&mut self) {
-		match File::open(&self.filename) {
-			Ok(mut input) => {
-				let mut vm = VM::new();
-				let mut contents = String::new(); 
+/// Reads a program from either an object file or source, exiting with the
+/// relevant errors if it can't be loaded.
+pub fn load(filename: &str) -> Program {
+	let bytes = read(filename);
+	if bytecode::is_object(&bytes) {
+		match bytecode::load(&bytes) {
+			Ok((instructions, word_size)) => Program { instructions, word_size: Some(word_size), source: None },
+			Err(error) => {
+				eprintln!("{}: {}", filename, error);
+				process::exit(1);
+			}
+		}
+	} else {
+		from_source(filename, String::from_utf8_lossy(&bytes).into_owned())
+	}
+}
+
+/// Compiles source into a program, exiting with rendered diagnostics on
+/// failure.
+pub fn from_source(filename: &str, source: String) -> Program {
+	let (instructions, spans) = compile_spanned(filename, &source).into_iter().map(|i| (i.node, i.span)).unzip();
+	Program { instructions, word_size: None, source: Some((source, spans)) }
+}
+
+/// Reports the problems static analysis finds in a program, returning whether
+/// any of them are errors that should stop it from running.
+pub fn check(filename: &str, program: &Program) -> bool {
+	let issues = analysis::verify(&program.instructions);
+	for issue in &issues {
+		program.report(filename, issue);
+	}
+	issues.iter().any(|issue| issue.kind.is_error())
+}
 
-				input.read_to_string(&mut contents).unwrap();
+/// Reads a whole file, exiting if it can't be read.
+pub fn read(filename: &str) -> Vec<u8> {
+	let mut bytes = vec![];
+	if let Err(error) = File::open(filename).and_then(|mut input| input.read_to_end(&mut bytes)) {
+		eprintln!("{}: {}", filename, error);
+		process::exit(1);
+	}
+	bytes
+}
 
-				let tokens = lexer::tokenize(contents.as_ref());
+/// Tokenizes and parses source, keeping where each instruction is, and exits
+/// with rendered diagnostics on failure.
+pub fn compile_spanned(filename: &str, contents: &str) -> Vec<Spanned<Instruction>> {
+	match lexer::tokenize(contents).and_then(parser::parse_spanned) {
+		Ok(program) => program,
+		Err(diagnostics) => {
+			for diagnostic in diagnostics {
+				eprint!("{}", diagnostic.render(filename, contents));
+			}
+			process::exit(1);
+		}
+	}
+}
 
-				let program = parser::parse(tokens);
+impl Runner {
+	pub fn new(filename: String, config: Config) -> Runner {
+		Runner {
+			filename,
+			config
+		}
+	}
 
-				vm.run(program);
-			},
-			Err(error) => panic!("{}", error),
+	pub fn run(&mut self) {
+		let program = load(&self.filename);
+		if check(&self.filename, &program) {
+			process::exit(1);
+		}
+		if let Some(word_size) = program.word_size {
+			self.config.word_size = word_size;
+		}
+		let mut vm = VM::with_config(self.config.clone());
+
+		if let Err(error) = vm.run(program.instructions) {
+			eprintln!("VMError: {}", error);
+			process::exit(1);
 		}
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::collections::HashMap;
+use instructions::{Instruction, BranchType, ControlType};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum IssueKind {
+	DuplicateLabel(String),
+	UndefinedLabel(String),
+	MissingMainLabel,
+	/// The first of a run of instructions that can't be reached from `main`.
+	Unreachable,
+	/// An instruction that execution can continue past, off the end of the
+	/// program.
+	FallsOffEnd,
+}
+
+/// A problem found by `verify`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Issue {
+	pub kind: IssueKind,
+	/// Index of the offending instruction, if there is one.
+	pub position: Option<usize>,
+}
+
+impl IssueKind {
+	/// Whether the program can't run correctly, as opposed to being suspicious.
+	pub fn is_error(&self) -> bool {
+		match *self {
+			IssueKind::DuplicateLabel(_) | IssueKind::UndefinedLabel(_) | IssueKind::MissingMainLabel => true,
+			IssueKind::Unreachable | IssueKind::FallsOffEnd => false,
+		}
+	}
+}
+
+impl fmt::Display for IssueKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			IssueKind::DuplicateLabel(ref label) => write!(f, "label `{}` is defined more than once", label),
+			IssueKind::UndefinedLabel(ref label) => write!(f, "undefined label `{}`", label),
+			IssueKind::MissingMainLabel => write!(f, "program has no `main` label"),
+			IssueKind::Unreachable => write!(f, "unreachable code"),
+			IssueKind::FallsOffEnd => write!(f, "execution can run past the end of the program without reaching `hlt`"),
+		}
+	}
+}
+
+/// Checks a whole program before it runs, for problems the VM would otherwise
+/// only find, if at all, when it reaches them. Errors come before warnings,
+/// each in program order.
+pub fn verify(program: &[Instruction]) -> Vec<Issue> {
+	let mut issues = vec![];
+	let labels = labels(program);
+	if !labels.contains_key("main") {
+		issues.push(Issue { kind: IssueKind::MissingMainLabel, position: None });
+	}
+	let mut defined = HashMap::new();
+	for (position, instruction) in program.iter().enumerate() {
+		let kind = match *instruction {
+			Instruction::Control(ControlType::LBL(ref label)) if defined.insert(label, position).is_some() => IssueKind::DuplicateLabel(label.clone()),
+			Instruction::Branch(_, ref label) if !labels.contains_key(label.as_str()) => IssueKind::UndefinedLabel(label.clone()),
+			_ => continue,
+		};
+		issues.push(Issue { kind, position: Some(position) });
+	}
+	if labels.contains_key("main") {
+		let reachable = reachable(program);
+		let mut in_run = false;
+		for (position, instruction) in program.iter().enumerate() {
+			match *instruction {
+				// Labels and `.data` aren't code, so don't start or end a run.
+				Instruction::Control(_) => {},
+				_ if reachable[position] => in_run = false,
+				_ if !in_run => {
+					in_run = true;
+					issues.push(Issue { kind: IssueKind::Unreachable, position: Some(position) });
+				},
+				_ => {},
+			}
+		}
+		if let Some(position) = falls_off_end(program) {
+			issues.push(Issue { kind: IssueKind::FallsOffEnd, position: Some(position) });
+		}
+	}
+	issues
+}
+
+/// The position of every label in a program. A label defined more than once,
+/// which `verify` reports and the VM rejects, is at its last definition.
+pub fn labels(program: &[Instruction]) -> HashMap<&str, usize> {
+	let mut labels = HashMap::new();
+	for (position, instruction) in program.iter().enumerate() {
+		if let Instruction::Control(ControlType::LBL(ref label)) = *instruction {
+			labels.insert(label.as_str(), position);
+		}
+	}
+	labels
+}
+
+/// The instructions that may execute straight after the one at `index`,
+/// where `program.len()` means execution runs past the end. Calls are assumed
+/// to return, and branches to undefined labels go nowhere.
+pub fn successors(program: &[Instruction], labels: &HashMap<&str, usize>, index: usize) -> Vec<usize> {
+	let next = index + 1;
+	match program[index] {
+		Instruction::HLT | Instruction::RET => vec![],
+		Instruction::Branch(ref kind, ref label) => {
+			let target = labels.get(label.as_str()).cloned();
+			match *kind {
+				BranchType::UNCONDITIONAL => target.into_iter().collect(),
+				_ => target.into_iter().chain(Some(next)).collect(),
+			}
+		},
+		_ => vec![next],
+	}
+}
+
+/// Whether each instruction can be reached by following control flow from
+/// `main`. Everything is unreachable if there is no `main` label.
+pub fn reachable(program: &[Instruction]) -> Vec<bool> {
+	let labels = labels(program);
+	let mut reachable = vec![false; program.len()];
+	let mut pending = labels.get("main").cloned().into_iter().collect::<Vec<_>>();
+	while let Some(index) = pending.pop() {
+		if index >= program.len() || reachable[index] {
+			continue;
+		}
+		reachable[index] = true;
+		pending.extend(successors(program, &labels, index));
+	}
+	reachable
+}
+
+/// Finds an instruction reachable from `main` that execution can continue
+/// past, running off the end of the program without reaching `hlt`.
+pub fn falls_off_end(program: &[Instruction]) -> Option<usize> {
+	let last = program.len().checked_sub(1)?;
+	if reachable(program)[last] && successors(program, &labels(program), last).contains(&program.len()) {
+		Some(last)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lexer::tokenize;
+	use parser::parse;
+
+	fn program(source: &str) -> Vec<Instruction> {
+		parse(tokenize(source).unwrap()).unwrap()
+	}
+	#[test]
+	fn reachable_from_main() {
+		let program = program("str 1 ra\nmain:\njz ra skip\ncall sub\nskip:\nhlt\nout ra\nsub:\nret\njmp main");
+		assert_eq!(reachable(&program), vec![false, true, true, true, true, true, false, true, true, false]);
+	}
+	#[test]
+	fn falls_off() {
+		assert_eq!(falls_off_end(&program("main:\nhlt")), None);
+		assert_eq!(falls_off_end(&program("main:\njz ra end\nhlt\nend:\nout ra")), Some(4));
+		assert_eq!(falls_off_end(&program("main:\njz ra end\nhlt\nend:")), Some(3));
+		assert_eq!(falls_off_end(&program("main:\njmp main\nout ra")), None);
+		assert_eq!(falls_off_end(&program("main:\nhlt\nsub:\nout ra")), None);
+		assert_eq!(falls_off_end(&program("main:\njnz ra main\nhlt\nsub:\nret")), None);
+		assert_eq!(falls_off_end(&program("main:\ncall sub\nhlt\nsub:\nout ra")), Some(4));
+		assert_eq!(falls_off_end(&[]), None);
+	}
+	#[test]
+	fn verify_program() {
+		let issue = |kind, position| Issue { kind, position };
+		assert_eq!(verify(&program("main:\njz ra end\nout ra\nend:\nhlt")), vec![]);
+		assert_eq!(verify(&program("str 1 ra\nmain:\njz ra end\ncall sub\nhlt\nout ra\nnop\nend:\nend:\nout rb\njmp nowhere\nsub:\nret")), vec![
+			issue(IssueKind::DuplicateLabel("end".to_string()), Some(8)),
+			issue(IssueKind::UndefinedLabel("nowhere".to_string()), Some(10)),
+			issue(IssueKind::Unreachable, Some(0)),
+			issue(IssueKind::Unreachable, Some(5)),
+		]);
+		assert_eq!(verify(&program("start:\njmp start\nhlt")), vec![issue(IssueKind::MissingMainLabel, None)]);
+		assert_eq!(verify(&program("main:\njz ra end\nhlt\nend:\nout ra")), vec![issue(IssueKind::FallsOffEnd, Some(4))]);
+	}
+}
@@ -0,0 +1,850 @@
+use std::fmt;
+use std::error;
+use std::collections::{HashMap, BTreeMap};
+use instructions::Instruction;
+use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, StackType, Operand, Address};
+use registers::{Register, FloatRegister};
+use vm::WordSize;
+use instructions::{FloatArithmaticType, FloatUnaryType, FloatAssignmentType, FloatIOType, ConversionType};
+
+// Object file layout, all integers little-endian:
+//
+//   magic     4 bytes  "\x7fTBC"
+//   version   u16
+//   flags     u16      bit 0 set for 64-bit words, the rest reserved
+//   entry     u32      code offset of `main`
+//   constants u32 count, followed by that many words, i32 or i64
+//   code      u32 length in bytes, followed by the encoded instructions
+//
+// Labels are not encoded; branches carry the code offset of their target.
+// Arithmetic and bitwise source operands are either a register index, or
+// `IMMEDIATE` followed by a u32 constant index. Float values are stored inline
+// as their IEEE 754 bits.
+pub const MAGIC: &[u8; 4] = b"\x7fTBC";
+pub const VERSION: u16 = 1;
+const IMMEDIATE: u8 = 0xff;
+const WIDE: u16 = 0x1;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum Opcode {
+	NOP = 0x00,
+	HLT = 0x01,
+	RET = 0x02,
+
+	OUT = 0x10,
+	IN  = 0x11,
+	OUTN = 0x12,
+	OUTC = 0x13,
+	PRINTS = 0x14,
+
+	ADD = 0x20,
+	SUB = 0x21,
+	MUL = 0x22,
+	DIV = 0x23,
+	MAX = 0x24,
+	MIN = 0x25,
+	MOD = 0x26,
+	NEG = 0x27,
+	ABS = 0x28,
+
+	AND = 0x30,
+	OR  = 0x31,
+	XOR = 0x32,
+	SHL = 0x33,
+	SHR = 0x34,
+	NOT = 0x35,
+	SHRU = 0x36,
+
+	JMP = 0x40,
+	JZ  = 0x41,
+	JNZ = 0x42,
+	CALL = 0x43,
+	JEQ = 0x44,
+	JNE = 0x45,
+	JLT = 0x46,
+	JLE = 0x47,
+	JGT = 0x48,
+	JGE = 0x49,
+	JLTU = 0x4a,
+	JLEU = 0x4b,
+	JGTU = 0x4c,
+	JGEU = 0x4d,
+
+	STR = 0x50,
+	CPY = 0x51,
+
+	LD  = 0x60,
+	ST  = 0x61,
+
+	PUSH = 0x70,
+	POP = 0x71,
+
+	DATA = 0x80,
+
+	SEQ = 0x90,
+	SNE = 0x91,
+	SLT = 0x92,
+	SLE = 0x93,
+	SGT = 0x94,
+	SGE = 0x95,
+	SLTU = 0x96,
+	SLEU = 0x97,
+	SGTU = 0x98,
+	SGEU = 0x99,
+
+	FADD = 0xa0,
+	FSUB = 0xa1,
+	FMUL = 0xa2,
+	FDIV = 0xa3,
+	FSQRT = 0xa4,
+	FNEG = 0xa5,
+	FABS = 0xa6,
+	FSTR = 0xa8,
+	FCPY = 0xa9,
+	FOUT = 0xaa,
+	FOUTN = 0xab,
+	ITOF = 0xac,
+	FTOI = 0xad,
+}
+
+const OPCODES: &[Opcode] = &[
+	Opcode::NOP, Opcode::HLT, Opcode::RET,
+	Opcode::OUT, Opcode::IN, Opcode::OUTN, Opcode::OUTC, Opcode::PRINTS,
+	Opcode::ADD, Opcode::SUB, Opcode::MUL, Opcode::DIV, Opcode::MAX, Opcode::MIN, Opcode::MOD, Opcode::NEG, Opcode::ABS,
+	Opcode::AND, Opcode::OR, Opcode::XOR, Opcode::SHL, Opcode::SHR, Opcode::NOT, Opcode::SHRU,
+	Opcode::JMP, Opcode::JZ, Opcode::JNZ, Opcode::CALL,
+	Opcode::JEQ, Opcode::JNE, Opcode::JLT, Opcode::JLE, Opcode::JGT, Opcode::JGE, Opcode::JLTU, Opcode::JLEU, Opcode::JGTU, Opcode::JGEU,
+	Opcode::STR, Opcode::CPY,
+	Opcode::LD, Opcode::ST,
+	Opcode::PUSH, Opcode::POP,
+	Opcode::DATA,
+	Opcode::SEQ, Opcode::SNE, Opcode::SLT, Opcode::SLE, Opcode::SGT, Opcode::SGE, Opcode::SLTU, Opcode::SLEU, Opcode::SGTU, Opcode::SGEU,
+	Opcode::FADD, Opcode::FSUB, Opcode::FMUL, Opcode::FDIV, Opcode::FSQRT, Opcode::FNEG, Opcode::FABS,
+	Opcode::FSTR, Opcode::FCPY, Opcode::FOUT, Opcode::FOUTN, Opcode::ITOF, Opcode::FTOI,
+];
+
+impl Opcode {
+	pub fn from_byte(byte: u8) -> Option<Opcode> {
+		OPCODES.iter().find(|&&op| op as u8 == byte).copied()
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum BytecodeError {
+	BadMagic,
+	UnsupportedVersion(u16),
+	Truncated,
+	InvalidOpcode(u8, usize),
+	InvalidRegister(u8, usize),
+	InvalidConstant(u32, usize),
+	InvalidJumpTarget(u32, usize),
+	InvalidText(usize),
+	ConstantOutOfRange(i64),
+	UndefinedLabel(String),
+	DuplicateLabel(String),
+	MissingMainLabel,
+}
+
+impl fmt::Display for BytecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			BytecodeError::BadMagic => write!(f, "not a thermite object file"),
+			BytecodeError::UnsupportedVersion(version) => write!(f, "unsupported object file version {}", version),
+			BytecodeError::Truncated => write!(f, "object file is truncated"),
+			BytecodeError::InvalidOpcode(op, offset) => write!(f, "invalid opcode 0x{:02x} at offset {:#06x}", op, offset),
+			BytecodeError::InvalidRegister(register, offset) => write!(f, "invalid register {} at offset {:#06x}", register, offset),
+			BytecodeError::InvalidConstant(index, offset) => write!(f, "invalid constant index {} at offset {:#06x}", index, offset),
+			BytecodeError::InvalidJumpTarget(target, offset) => write!(f, "invalid jump target {:#06x} at offset {:#06x}", target, offset),
+			BytecodeError::InvalidText(offset) => write!(f, "invalid UTF-8 in data at offset {:#06x}", offset),
+			BytecodeError::ConstantOutOfRange(value) => write!(f, "literal {} does not fit in 32 bits", value),
+			BytecodeError::UndefinedLabel(ref label) => write!(f, "undefined label `{}`", label),
+			BytecodeError::DuplicateLabel(ref label) => write!(f, "label `{}` is defined more than once", label),
+			BytecodeError::MissingMainLabel => write!(f, "program has no `main` label"),
+		}
+	}
+}
+
+impl error::Error for BytecodeError {}
+
+/// An assembled program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Object {
+	pub word_size: WordSize,
+	pub entry: u32,
+	pub constants: Vec<i64>,
+	pub code: Vec<u8>,
+}
+
+/// A decoded instruction and the code bytes it was decoded from. Labels are
+/// synthesized at `main` and at every jump target, and have a length of zero.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Decoded {
+	pub offset: usize,
+	pub len: usize,
+	pub instruction: Instruction,
+}
+
+/// Checks whether `bytes` looks like an object file rather than source text.
+pub fn is_object(bytes: &[u8]) -> bool {
+	bytes.starts_with(MAGIC)
+}
+
+/// Assembles and serializes a program in one step.
+pub fn assemble(program: &[Instruction], word_size: WordSize) -> Result<Vec<u8>, BytecodeError> {
+	Ok(Object::assemble(program, word_size)?.to_bytes())
+}
+
+/// Deserializes and decodes an object file into a program the VM can run,
+/// along with the word size it was assembled for.
+pub fn load(bytes: &[u8]) -> Result<(Vec<Instruction>, WordSize), BytecodeError> {
+	let object = Object::from_bytes(bytes)?;
+	let decoded = object.decode()?;
+	Ok((decoded.into_iter().map(|d| d.instruction).collect(), object.word_size))
+}
+
+struct Encoder {
+	word_size: WordSize,
+	code: Vec<u8>,
+	constants: Vec<i64>,
+	pool: HashMap<i64, u32>,
+	labels: HashMap<String, u32>,
+	patches: Vec<(usize, String)>,
+}
+
+impl Encoder {
+	fn op(&mut self, op: Opcode) {
+		self.code.push(op as u8);
+	}
+	fn register(&mut self, register: Register) {
+		self.code.push(register.index() as u8);
+	}
+	fn float_register(&mut self, register: FloatRegister) {
+		self.code.push(register.index() as u8);
+	}
+	fn u32(&mut self, value: u32) {
+		self.code.extend_from_slice(&value.to_le_bytes());
+	}
+	fn f64(&mut self, value: f64) {
+		self.code.extend_from_slice(&value.to_bits().to_le_bytes());
+	}
+	fn operand(&mut self, operand: Operand) {
+		match operand {
+			Operand::Reg(register) => self.register(register),
+			Operand::Imm(value) => {
+				self.code.push(IMMEDIATE);
+				self.constant(value);
+			},
+		}
+	}
+	fn address(&mut self, address: Address) {
+		self.register(address.base);
+		self.code.extend_from_slice(&address.offset.to_le_bytes());
+	}
+	fn constant(&mut self, value: i64) {
+		// 32-bit objects store the sign extended low bits, so `0xffffffff`
+		// and `-1` share a constant.
+		let value = self.word_size.truncate(value);
+		let next = self.constants.len() as u32;
+		let index = *self.pool.entry(value).or_insert(next);
+		if index == next {
+			self.constants.push(value);
+		}
+		self.u32(index);
+	}
+	fn target(&mut self, label: &str) {
+		self.patches.push((self.code.len(), label.to_string()));
+		self.u32(0);
+	}
+	fn encode(&mut self, instruction: &Instruction) -> Result<(), BytecodeError> {
+		match *instruction {
+			Instruction::NOP => self.op(Opcode::NOP),
+			Instruction::HLT => self.op(Opcode::HLT),
+			Instruction::RET => self.op(Opcode::RET),
+			Instruction::IO(ref kind, register) => {
+				self.op(match *kind {
+					IOType::OUT => Opcode::OUT,
+					IOType::IN => Opcode::IN,
+					IOType::OUTN => Opcode::OUTN,
+					IOType::OUTC => Opcode::OUTC,
+					IOType::PRINTS => Opcode::PRINTS,
+				});
+				self.register(register);
+			},
+			Instruction::Arithmatic(ref kind, source, target, destination) => {
+				self.op(match *kind {
+					ArithmaticType::ADD => Opcode::ADD,
+					ArithmaticType::SUB => Opcode::SUB,
+					ArithmaticType::MUL => Opcode::MUL,
+					ArithmaticType::DIV => Opcode::DIV,
+					ArithmaticType::MAX => Opcode::MAX,
+					ArithmaticType::MIN => Opcode::MIN,
+					ArithmaticType::MOD => Opcode::MOD,
+				});
+				self.operand(source);
+				self.operand(target);
+				self.register(destination);
+			},
+			Instruction::Bitwise(ref kind, source, target, destination) => {
+				self.op(match *kind {
+					BitwiseType::AND => Opcode::AND,
+					BitwiseType::OR => Opcode::OR,
+					BitwiseType::XOR => Opcode::XOR,
+					BitwiseType::SHIFT(ShiftType::LEFT) => Opcode::SHL,
+					BitwiseType::SHIFT(ShiftType::RIGHT) => Opcode::SHR,
+					BitwiseType::SHIFT(ShiftType::LOGICAL) => Opcode::SHRU,
+				});
+				self.operand(source);
+				self.operand(target);
+				self.register(destination);
+			},
+			Instruction::Unary(ref kind, source, destination) => {
+				self.op(match *kind {
+					UnaryType::NEG => Opcode::NEG,
+					UnaryType::NOT => Opcode::NOT,
+					UnaryType::ABS => Opcode::ABS,
+				});
+				self.register(source);
+				self.register(destination);
+			},
+			Instruction::Compare(ref kind, source, target, destination) => {
+				self.op(match *kind {
+					ComparisonType::EQ => Opcode::SEQ,
+					ComparisonType::NE => Opcode::SNE,
+					ComparisonType::LT => Opcode::SLT,
+					ComparisonType::LE => Opcode::SLE,
+					ComparisonType::GT => Opcode::SGT,
+					ComparisonType::GE => Opcode::SGE,
+					ComparisonType::LTU => Opcode::SLTU,
+					ComparisonType::LEU => Opcode::SLEU,
+					ComparisonType::GTU => Opcode::SGTU,
+					ComparisonType::GEU => Opcode::SGEU,
+				});
+				self.register(source);
+				self.register(target);
+				self.register(destination);
+			},
+			Instruction::Branch(ref kind, ref label) => {
+				match *kind {
+					BranchType::UNCONDITIONAL => self.op(Opcode::JMP),
+					BranchType::ZERO(register) => {
+						self.op(Opcode::JZ);
+						self.register(register);
+					},
+					BranchType::NOTZERO(register) => {
+						self.op(Opcode::JNZ);
+						self.register(register);
+					},
+					BranchType::COMPARE(ref kind, source, target) => {
+						self.op(match *kind {
+							ComparisonType::EQ => Opcode::JEQ,
+							ComparisonType::NE => Opcode::JNE,
+							ComparisonType::LT => Opcode::JLT,
+							ComparisonType::LE => Opcode::JLE,
+							ComparisonType::GT => Opcode::JGT,
+							ComparisonType::GE => Opcode::JGE,
+							ComparisonType::LTU => Opcode::JLTU,
+							ComparisonType::LEU => Opcode::JLEU,
+							ComparisonType::GTU => Opcode::JGTU,
+							ComparisonType::GEU => Opcode::JGEU,
+						});
+						self.register(source);
+						self.register(target);
+					},
+					BranchType::CALL => self.op(Opcode::CALL),
+				}
+				self.target(label);
+			},
+			Instruction::Control(ControlType::LBL(ref label)) => {
+				if self.labels.insert(label.clone(), self.code.len() as u32).is_some() {
+					return Err(BytecodeError::DuplicateLabel(label.clone()));
+				}
+			},
+			Instruction::Control(ControlType::DATA(address, ref text)) => {
+				self.op(Opcode::DATA);
+				self.u32(address as u32);
+				self.u32(text.len() as u32);
+				self.code.extend_from_slice(text.as_bytes());
+			},
+			Instruction::Assignment(ref kind, register) => {
+				match *kind {
+					AssignmentType::STR(value) => {
+						self.op(Opcode::STR);
+						self.constant(value);
+					},
+					AssignmentType::CPY(source) => {
+						self.op(Opcode::CPY);
+						self.register(source);
+					},
+				}
+				self.register(register);
+			},
+			Instruction::Memory(ref kind, register, address) => {
+				self.op(match *kind {
+					MemoryType::LOAD => Opcode::LD,
+					MemoryType::STORE => Opcode::ST,
+				});
+				self.register(register);
+				self.address(address);
+			},
+			Instruction::Stack(ref kind, register) => {
+				self.op(match *kind {
+					StackType::PUSH => Opcode::PUSH,
+					StackType::POP => Opcode::POP,
+				});
+				self.register(register);
+			},
+			Instruction::FloatArithmatic(ref kind, source, target, destination) => {
+				self.op(match *kind {
+					FloatArithmaticType::ADD => Opcode::FADD,
+					FloatArithmaticType::SUB => Opcode::FSUB,
+					FloatArithmaticType::MUL => Opcode::FMUL,
+					FloatArithmaticType::DIV => Opcode::FDIV,
+				});
+				self.float_register(source);
+				self.float_register(target);
+				self.float_register(destination);
+			},
+			Instruction::FloatUnary(ref kind, source, destination) => {
+				self.op(match *kind {
+					FloatUnaryType::SQRT => Opcode::FSQRT,
+					FloatUnaryType::NEG => Opcode::FNEG,
+					FloatUnaryType::ABS => Opcode::FABS,
+				});
+				self.float_register(source);
+				self.float_register(destination);
+			},
+			Instruction::FloatAssignment(ref kind, register) => {
+				match *kind {
+					FloatAssignmentType::STR(value) => {
+						self.op(Opcode::FSTR);
+						self.f64(value);
+					},
+					FloatAssignmentType::CPY(source) => {
+						self.op(Opcode::FCPY);
+						self.float_register(source);
+					},
+				}
+				self.float_register(register);
+			},
+			Instruction::FloatIO(ref kind, register) => {
+				self.op(match *kind {
+					FloatIOType::OUT => Opcode::FOUT,
+					FloatIOType::OUTN => Opcode::FOUTN,
+				});
+				self.float_register(register);
+			},
+			Instruction::Conversion(ref kind, register, float) => {
+				self.op(match *kind {
+					ConversionType::TOFLOAT => Opcode::ITOF,
+					ConversionType::TOINT => Opcode::FTOI,
+				});
+				self.register(register);
+				self.float_register(float);
+			},
+		}
+		Ok(())
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+		if self.bytes.len() - self.pos < len {
+			return Err(BytecodeError::Truncated);
+		}
+		let bytes = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(bytes)
+	}
+	fn u8(&mut self) -> Result<u8, BytecodeError> {
+		Ok(self.take(1)?[0])
+	}
+	fn u16(&mut self) -> Result<u16, BytecodeError> {
+		let bytes = self.take(2)?;
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+	fn u32(&mut self) -> Result<u32, BytecodeError> {
+		let bytes = self.take(4)?;
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+	fn u64(&mut self) -> Result<u64, BytecodeError> {
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(self.take(8)?);
+		Ok(u64::from_le_bytes(bytes))
+	}
+	fn f64(&mut self) -> Result<f64, BytecodeError> {
+		Ok(f64::from_bits(self.u64()?))
+	}
+	fn float_register(&mut self) -> Result<FloatRegister, BytecodeError> {
+		let offset = self.pos;
+		let index = self.u8()?;
+		FloatRegister::from_index(index).ok_or(BytecodeError::InvalidRegister(index, offset))
+	}
+	fn address(&mut self) -> Result<Address, BytecodeError> {
+		let base = self.register()?;
+		let offset = self.u32()? as i32;
+		Ok(Address { base, offset })
+	}
+	fn operand(&mut self, constants: &[i64]) -> Result<Operand, BytecodeError> {
+		let offset = self.pos;
+		if self.bytes.get(offset) != Some(&IMMEDIATE) {
+			return Ok(Operand::Reg(self.register()?));
+		}
+		self.pos += 1;
+		let index = self.u32()?;
+		constants.get(index as usize).map(|&value| Operand::Imm(value)).ok_or(BytecodeError::InvalidConstant(index, offset))
+	}
+	fn register(&mut self) -> Result<Register, BytecodeError> {
+		let offset = self.pos;
+		let index = self.u8()?;
+		Register::from_index(index).ok_or(BytecodeError::InvalidRegister(index, offset))
+	}
+}
+
+impl Object {
+	pub fn assemble(program: &[Instruction], word_size: WordSize) -> Result<Object, BytecodeError> {
+		if let Some(value) = program.iter().flat_map(|instruction| instruction.literals()).find(|&value| !word_size.fits(value)) {
+			return Err(BytecodeError::ConstantOutOfRange(value));
+		}
+		let mut encoder = Encoder {
+			word_size,
+			code: Vec::new(),
+			constants: Vec::new(),
+			pool: HashMap::new(),
+			labels: HashMap::new(),
+			patches: Vec::new(),
+		};
+		for instruction in program {
+			encoder.encode(instruction)?;
+		}
+		for (position, label) in encoder.patches {
+			match encoder.labels.get(&label) {
+				Some(&target) => encoder.code[position..position + 4].copy_from_slice(&target.to_le_bytes()),
+				None => return Err(BytecodeError::UndefinedLabel(label)),
+			}
+		}
+		match encoder.labels.get("main") {
+			Some(&entry) => Ok(Object { word_size, entry, constants: encoder.constants, code: encoder.code }),
+			None => Err(BytecodeError::MissingMainLabel),
+		}
+	}
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend_from_slice(&VERSION.to_le_bytes());
+		let flags = match self.word_size {
+			WordSize::Bits32 => 0,
+			WordSize::Bits64 => WIDE,
+		};
+		bytes.extend_from_slice(&flags.to_le_bytes());
+		bytes.extend_from_slice(&self.entry.to_le_bytes());
+		bytes.extend_from_slice(&(self.constants.len() as u32).to_le_bytes());
+		for &constant in &self.constants {
+			match self.word_size {
+				WordSize::Bits32 => bytes.extend_from_slice(&(constant as i32).to_le_bytes()),
+				WordSize::Bits64 => bytes.extend_from_slice(&constant.to_le_bytes()),
+			}
+		}
+		bytes.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+		bytes.extend_from_slice(&self.code);
+		bytes
+	}
+	pub fn from_bytes(bytes: &[u8]) -> Result<Object, BytecodeError> {
+		if !is_object(bytes) {
+			return Err(BytecodeError::BadMagic);
+		}
+		let mut reader = Reader { bytes, pos: MAGIC.len() };
+		let version = reader.u16()?;
+		if version != VERSION {
+			return Err(BytecodeError::UnsupportedVersion(version));
+		}
+		let word_size = if reader.u16()? & WIDE != 0 { WordSize::Bits64 } else { WordSize::Bits32 };
+		let entry = reader.u32()?;
+		let count = reader.u32()?;
+		let mut constants = Vec::new();
+		for _ in 0..count {
+			constants.push(match word_size {
+				WordSize::Bits32 => reader.u32()? as i32 as i64,
+				WordSize::Bits64 => reader.u64()? as i64,
+			});
+		}
+		let len = reader.u32()? as usize;
+		let code = reader.take(len)?.to_vec();
+		Ok(Object { word_size, entry, constants, code })
+	}
+	/// Decodes the code section, replacing jump targets with synthesized
+	/// labels: `main` for the entry point and `l<offset>` for the rest.
+	pub fn decode(&self) -> Result<Vec<Decoded>, BytecodeError> {
+		let mut reader = Reader { bytes: &self.code, pos: 0 };
+		let mut decoded = Vec::new();
+		let mut targets = Vec::new();
+
+		while reader.pos < self.code.len() {
+			let offset = reader.pos;
+			let byte = reader.u8()?;
+			let op = Opcode::from_byte(byte).ok_or(BytecodeError::InvalidOpcode(byte, offset))?;
+			let instruction = match op {
+				Opcode::NOP => Instruction::NOP,
+				Opcode::HLT => Instruction::HLT,
+				Opcode::RET => Instruction::RET,
+				Opcode::OUT => Instruction::IO(IOType::OUT, reader.register()?),
+				Opcode::IN => Instruction::IO(IOType::IN, reader.register()?),
+				Opcode::OUTN => Instruction::IO(IOType::OUTN, reader.register()?),
+				Opcode::OUTC => Instruction::IO(IOType::OUTC, reader.register()?),
+				Opcode::PRINTS => Instruction::IO(IOType::PRINTS, reader.register()?),
+				Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV | Opcode::MAX | Opcode::MIN | Opcode::MOD => {
+					let kind = match op {
+						Opcode::ADD => ArithmaticType::ADD,
+						Opcode::SUB => ArithmaticType::SUB,
+						Opcode::MUL => ArithmaticType::MUL,
+						Opcode::DIV => ArithmaticType::DIV,
+						Opcode::MAX => ArithmaticType::MAX,
+						Opcode::MIN => ArithmaticType::MIN,
+						_ => ArithmaticType::MOD,
+					};
+					Instruction::Arithmatic(kind, reader.operand(&self.constants)?, reader.operand(&self.constants)?, reader.register()?)
+				},
+				Opcode::AND | Opcode::OR | Opcode::XOR | Opcode::SHL | Opcode::SHR | Opcode::SHRU => {
+					let kind = match op {
+						Opcode::AND => BitwiseType::AND,
+						Opcode::OR => BitwiseType::OR,
+						Opcode::XOR => BitwiseType::XOR,
+						Opcode::SHL => BitwiseType::SHIFT(ShiftType::LEFT),
+						Opcode::SHR => BitwiseType::SHIFT(ShiftType::RIGHT),
+						_ => BitwiseType::SHIFT(ShiftType::LOGICAL),
+					};
+					Instruction::Bitwise(kind, reader.operand(&self.constants)?, reader.operand(&self.constants)?, reader.register()?)
+				},
+				Opcode::NEG | Opcode::NOT | Opcode::ABS => {
+					let kind = match op {
+						Opcode::NEG => UnaryType::NEG,
+						Opcode::NOT => UnaryType::NOT,
+						_ => UnaryType::ABS,
+					};
+					Instruction::Unary(kind, reader.register()?, reader.register()?)
+				},
+				Opcode::SEQ | Opcode::SNE | Opcode::SLT | Opcode::SLE | Opcode::SGT | Opcode::SGE |
+				Opcode::SLTU | Opcode::SLEU | Opcode::SGTU | Opcode::SGEU => {
+					let kind = match op {
+						Opcode::SEQ => ComparisonType::EQ,
+						Opcode::SNE => ComparisonType::NE,
+						Opcode::SLT => ComparisonType::LT,
+						Opcode::SLE => ComparisonType::LE,
+						Opcode::SGT => ComparisonType::GT,
+						Opcode::SGE => ComparisonType::GE,
+						Opcode::SLTU => ComparisonType::LTU,
+						Opcode::SLEU => ComparisonType::LEU,
+						Opcode::SGTU => ComparisonType::GTU,
+						_ => ComparisonType::GEU,
+					};
+					Instruction::Compare(kind, reader.register()?, reader.register()?, reader.register()?)
+				},
+				Opcode::JMP | Opcode::JZ | Opcode::JNZ | Opcode::CALL |
+				Opcode::JEQ | Opcode::JNE | Opcode::JLT | Opcode::JLE | Opcode::JGT | Opcode::JGE |
+				Opcode::JLTU | Opcode::JLEU | Opcode::JGTU | Opcode::JGEU => {
+					let kind = match op {
+						Opcode::JMP => BranchType::UNCONDITIONAL,
+						Opcode::CALL => BranchType::CALL,
+						Opcode::JZ => BranchType::ZERO(reader.register()?),
+						Opcode::JNZ => BranchType::NOTZERO(reader.register()?),
+						_ => {
+							let kind = match op {
+								Opcode::JEQ => ComparisonType::EQ,
+								Opcode::JNE => ComparisonType::NE,
+								Opcode::JLT => ComparisonType::LT,
+								Opcode::JLE => ComparisonType::LE,
+								Opcode::JGT => ComparisonType::GT,
+								Opcode::JGE => ComparisonType::GE,
+								Opcode::JLTU => ComparisonType::LTU,
+								Opcode::JLEU => ComparisonType::LEU,
+								Opcode::JGTU => ComparisonType::GTU,
+								_ => ComparisonType::GEU,
+							};
+							BranchType::COMPARE(kind, reader.register()?, reader.register()?)
+						},
+					};
+					let target = reader.u32()?;
+					targets.push((target, offset));
+					Instruction::Branch(kind, String::new())
+				},
+				Opcode::STR => {
+					let index = reader.u32()?;
+					let value = *self.constants.get(index as usize).ok_or(BytecodeError::InvalidConstant(index, offset))?;
+					Instruction::Assignment(AssignmentType::STR(value), reader.register()?)
+				},
+				Opcode::CPY => {
+					let source = reader.register()?;
+					Instruction::Assignment(AssignmentType::CPY(source), reader.register()?)
+				},
+				Opcode::LD => Instruction::Memory(MemoryType::LOAD, reader.register()?, reader.address()?),
+				Opcode::ST => Instruction::Memory(MemoryType::STORE, reader.register()?, reader.address()?),
+				Opcode::PUSH => Instruction::Stack(StackType::PUSH, reader.register()?),
+				Opcode::POP => Instruction::Stack(StackType::POP, reader.register()?),
+				Opcode::FADD | Opcode::FSUB | Opcode::FMUL | Opcode::FDIV => {
+					let kind = match op {
+						Opcode::FADD => FloatArithmaticType::ADD,
+						Opcode::FSUB => FloatArithmaticType::SUB,
+						Opcode::FMUL => FloatArithmaticType::MUL,
+						_ => FloatArithmaticType::DIV,
+					};
+					Instruction::FloatArithmatic(kind, reader.float_register()?, reader.float_register()?, reader.float_register()?)
+				},
+				Opcode::FSQRT | Opcode::FNEG | Opcode::FABS => {
+					let kind = match op {
+						Opcode::FSQRT => FloatUnaryType::SQRT,
+						Opcode::FNEG => FloatUnaryType::NEG,
+						_ => FloatUnaryType::ABS,
+					};
+					Instruction::FloatUnary(kind, reader.float_register()?, reader.float_register()?)
+				},
+				Opcode::FSTR => {
+					let value = reader.f64()?;
+					Instruction::FloatAssignment(FloatAssignmentType::STR(value), reader.float_register()?)
+				},
+				Opcode::FCPY => {
+					let source = reader.float_register()?;
+					Instruction::FloatAssignment(FloatAssignmentType::CPY(source), reader.float_register()?)
+				},
+				Opcode::FOUT => Instruction::FloatIO(FloatIOType::OUT, reader.float_register()?),
+				Opcode::FOUTN => Instruction::FloatIO(FloatIOType::OUTN, reader.float_register()?),
+				Opcode::ITOF => Instruction::Conversion(ConversionType::TOFLOAT, reader.register()?, reader.float_register()?),
+				Opcode::FTOI => Instruction::Conversion(ConversionType::TOINT, reader.register()?, reader.float_register()?),
+				Opcode::DATA => {
+					let address = reader.u32()? as i32;
+					let len = reader.u32()? as usize;
+					let text = String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| BytecodeError::InvalidText(offset))?;
+					Instruction::Control(ControlType::DATA(address, text))
+				},
+			};
+			decoded.push(Decoded { offset, len: reader.pos - offset, instruction });
+		}
+
+		let is_boundary = |target: u32| {
+			target as usize == self.code.len() || decoded.iter().any(|d| d.offset == target as usize)
+		};
+		let mut labels = BTreeMap::new();
+		if !is_boundary(self.entry) {
+			return Err(BytecodeError::InvalidJumpTarget(self.entry, 0));
+		}
+		labels.insert(self.entry, "main".to_string());
+		for &(target, offset) in &targets {
+			if !is_boundary(target) {
+				return Err(BytecodeError::InvalidJumpTarget(target, offset));
+			}
+			labels.entry(target).or_insert_with(|| format!("l{:04x}", target));
+		}
+
+		let mut target_labels = targets.iter().map(|&(target, _)| labels[&target].clone()).collect::<Vec<_>>().into_iter();
+		let mut output = Vec::new();
+		for mut d in decoded {
+			if let Some(label) = labels.remove(&(d.offset as u32)) {
+				output.push(Decoded { offset: d.offset, len: 0, instruction: Instruction::Control(ControlType::LBL(label)) });
+			}
+			if let Instruction::Branch(_, ref mut label) = d.instruction {
+				*label = target_labels.next().unwrap();
+			}
+			output.push(d);
+		}
+		for (target, label) in labels {
+			output.push(Decoded { offset: target as usize, len: 0, instruction: Instruction::Control(ControlType::LBL(label)) });
+		}
+		Ok(output)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lexer::tokenize;
+	use parser::parse;
+
+	fn program(source: &str) -> Vec<Instruction> {
+		parse(tokenize(source).unwrap()).unwrap()
+	}
+	#[test]
+	fn encode() {
+		let object = Object::assemble(&program("main:\nstr 7 ra\nstr 7 rb\nloop:\njnz ra loop\nhlt"), WordSize::Bits32).unwrap();
+		assert_eq!(object.entry, 0);
+		assert_eq!(object.constants, vec![7]);
+		assert_eq!(object.code, vec![0x50, 0, 0, 0, 0, 0,
+			0x50, 0, 0, 0, 0, 1,
+			0x42, 0, 12, 0, 0, 0,
+			0x01]);
+	}
+	#[test]
+	fn round_trip() {
+		let source = program("start:\nstr 12 ra\nstr 1 rb\nmain:\nfactorial:\njz ra end\nmul rb ra rb\nstr 1 rc\nsub ra rc ra\njmp factorial\nend:\nout rb\nhlt");
+		let bytes = assemble(&source, WordSize::Bits32).unwrap();
+		assert!(is_object(&bytes));
+		assert_eq!(load(&bytes).unwrap().0, program("str 12 ra\nstr 1 rb\nmain:\njz ra l0025\nmul rb ra rb\nstr 1 rc\nsub ra rc ra\njmp main\nl0025:\nout rb\nhlt"));
+	}
+	#[test]
+	fn data() {
+		let source = program(".data 16 \"hi\"\nmain:\nstr 16 ra\nprints ra\nhlt");
+		let mut object = Object::assemble(&source, WordSize::Bits32).unwrap();
+		assert_eq!(&object.code[..11], &[0x80, 16, 0, 0, 0, 2, 0, 0, 0, b'h', b'i']);
+		assert_eq!(object.decode().unwrap().into_iter().map(|d| d.instruction).collect::<Vec<_>>(), program(".data 16 \"hi\"\nmain:\nstr 16 ra\nprints ra\nhlt"));
+		object.code[9] = 0xff;
+		assert_eq!(object.decode(), Err(BytecodeError::InvalidText(0)));
+	}
+	#[test]
+	fn immediate() {
+		let source = program("main:\nadd ra 1 ra\nshl 1 rb rc\nhlt");
+		let object = Object::assemble(&source, WordSize::Bits32).unwrap();
+		assert_eq!(object.constants, vec![1]);
+		assert_eq!(object.code, vec![0x20, 0, 0xff, 0, 0, 0, 0, 0,
+			0x33, 0xff, 0, 0, 0, 0, 1, 2,
+			0x01]);
+		assert_eq!(load(&object.to_bytes()).unwrap().0, source);
+	}
+	#[test]
+	fn word_size() {
+		let source = program("main:\nstr 0xffffffff ra\nadd ra 5000000000 ra\nhlt");
+		assert_eq!(Object::assemble(&source, WordSize::Bits32), Err(BytecodeError::ConstantOutOfRange(5000000000)));
+		let bytes = assemble(&source, WordSize::Bits64).unwrap();
+		assert_eq!(&bytes[6..8], &[1, 0]);
+		assert_eq!(&bytes[16..24], &0xffffffffi64.to_le_bytes());
+		assert_eq!(load(&bytes).unwrap(), (source, WordSize::Bits64));
+		let bytes = assemble(&program("main:\nstr 0xffffffff ra\nstr -1 rb\nhlt"), WordSize::Bits32).unwrap();
+		assert_eq!(&bytes[12..20], &[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+		assert_eq!(load(&bytes).unwrap(), (program("main:\nstr -1 ra\nstr -1 rb\nhlt"), WordSize::Bits32));
+	}
+	#[test]
+	fn label_at_end() {
+		let bytes = assemble(&program("main:\njmp end\nend:"), WordSize::Bits32).unwrap();
+		assert_eq!(load(&bytes).unwrap().0, program("main:\njmp l0005\nl0005:"));
+	}
+	#[test]
+	fn undefined_label() {
+		assert_eq!(Object::assemble(&program("main:\njmp nowhere"), WordSize::Bits32), Err(BytecodeError::UndefinedLabel("nowhere".to_string())));
+	}
+	#[test]
+	fn duplicate_label() {
+		assert_eq!(Object::assemble(&program("main:\njmp end\nend:\nhlt\nend:\nout ra"), WordSize::Bits32), Err(BytecodeError::DuplicateLabel("end".to_string())));
+	}
+	#[test]
+	fn missing_main() {
+		assert_eq!(Object::assemble(&program("hlt"), WordSize::Bits32), Err(BytecodeError::MissingMainLabel));
+	}
+	#[test]
+	fn invalid_object() {
+		assert_eq!(load(b"#!vma"), Err(BytecodeError::BadMagic));
+		let mut bytes = assemble(&program("main:\nout ra\nhlt"), WordSize::Bits32).unwrap();
+		bytes.pop();
+		assert_eq!(load(&bytes), Err(BytecodeError::Truncated));
+		let len = bytes.len();
+		bytes[len - 2] = 0xff;
+		bytes.push(0x01);
+		assert_eq!(load(&bytes), Err(BytecodeError::InvalidOpcode(0xff, 0)));
+	}
+	#[test]
+	fn invalid_jump_target() {
+		let mut object = Object::assemble(&program("main:\nstr 1 ra\njmp main"), WordSize::Bits32).unwrap();
+		object.code[7] = 3;
+		assert_eq!(object.decode(), Err(BytecodeError::InvalidJumpTarget(3, 6)));
+	}
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// A region of source text. `column` counts tabs as four columns, matching the
+/// lexer, and `len` is measured in characters.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+	pub line: u32,
+	pub column: u32,
+	pub len: usize,
+}
+
+impl Span {
+	pub fn new(line: u32, column: u32, len: usize) -> Span {
+		Span {
+			line,
+			column,
+			len
+		}
+	}
+	/// The single column immediately following this span.
+	pub fn after(&self) -> Span {
+		Span::new(self.line, self.column + self.len as u32, 1)
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned<T> {
+	pub node: T,
+	pub span: Span,
+}
+
+impl<T> Spanned<T> {
+	pub fn new(node: T, span: Span) -> Spanned<T> {
+		Spanned {
+			node,
+			span
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Severity::Error => write!(f, "error"),
+			Severity::Warning => write!(f, "warning"),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub message: String,
+	pub span: Span,
+}
+
+impl Diagnostic {
+	pub fn new<S: Into<String>>(message: S, span: Span) -> Diagnostic {
+		Diagnostic {
+			severity: Severity::Error,
+			message: message.into(),
+			span
+		}
+	}
+	pub fn warning<S: Into<String>>(message: S, span: Span) -> Diagnostic {
+		Diagnostic {
+			severity: Severity::Warning,
+			..Diagnostic::new(message, span)
+		}
+	}
+	/// Renders the diagnostic with the offending source line and a caret
+	/// underneath the span.
+	pub fn render(&self, filename: &str, source: &str) -> String {
+		let line = source.lines()
+			.nth(self.span.line.saturating_sub(1) as usize)
+			.unwrap_or("")
+			.replace('\t', "    ");
+		let number = self.span.line.to_string();
+		let gutter = " ".repeat(number.len());
+
+		format!("{}: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+			self.severity, self.message,
+			gutter, filename, self.span.line, self.span.column,
+			gutter,
+			number, line,
+			gutter, " ".repeat(self.span.column.saturating_sub(1) as usize), "^".repeat(self.span.len.max(1)))
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[test]
+	fn render() {
+		let source = "main:\n\tadd ra rz rc\n";
+		let diagnostic = Diagnostic::new("unknown register `rz`", Span::new(2, 12, 2));
+		assert_eq!(diagnostic.render("test.vma", source), "error: unknown register `rz`\n --> test.vma:2:12\n  |\n2 |     add ra rz rc\n  |            ^^\n");
+	}
+	#[test]
+	fn render_warning() {
+		let diagnostic = Diagnostic::warning("execution can run past the end of the program", Span::new(2, 5, 3));
+		assert_eq!(diagnostic.render("test.vma", "main:\n\tout ra"), "warning: execution can run past the end of the program\n --> test.vma:2:5\n  |\n2 |     out ra\n  |     ^^^\n");
+	}
+	#[test]
+	fn display() {
+		let diagnostic = Diagnostic::new("unknown instruction `foo`", Span::new(3, 1, 3));
+		assert_eq!(diagnostic.to_string(), "3:1: unknown instruction `foo`");
+	}
+}
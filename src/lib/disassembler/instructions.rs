@@ -1,67 +1,183 @@
 use instructions::Instruction;
-use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType};
+use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, StackType, Operand, Address};
+use instructions::{FloatArithmaticType, FloatUnaryType, FloatAssignmentType, FloatIOType, ConversionType};
+use lexer::escape;
 use std::fmt;
 
 
 
 impl fmt::Display for Instruction {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
-			&Instruction::IO(ref kind, register) => {
-				let string = match kind {
-					&IOType::OUT => "out",
-					&IOType::IN => "in",
+		match *self {
+			Instruction::IO(ref kind, register) => {
+				let string = match *kind {
+					IOType::OUT => "out",
+					IOType::IN => "in",
+					IOType::OUTN => "outn",
+					IOType::OUTC => "outc",
+					IOType::PRINTS => "prints",
 				};
 				write!(f, "{} {}", string, register)
 			},
-			&Instruction::Arithmatic(ref kind, source, target, destination) => {
-				let string = match kind {
-					&ArithmaticType::ADD => "add",
-					&ArithmaticType::SUB => "sub",
-					&ArithmaticType::MUL => "mul",
-					&ArithmaticType::DIV => "div",
-					&ArithmaticType::MAX => "max",
-					&ArithmaticType::MIN => "min",
+			Instruction::Arithmatic(ref kind, source, target, destination) => {
+				let string = match *kind {
+					ArithmaticType::ADD => "add",
+					ArithmaticType::SUB => "sub",
+					ArithmaticType::MUL => "mul",
+					ArithmaticType::DIV => "div",
+					ArithmaticType::MAX => "max",
+					ArithmaticType::MIN => "min",
+					ArithmaticType::MOD => "mod",
 				};
 				write!(f, "{} {} {} {}", string, source, target, destination)
 			},
-			&Instruction::Bitwise(ref kind, source, target, destination) => {
-				let string = match kind {
-					&BitwiseType::AND => "and",
-					&BitwiseType::OR  => "or",
-					&BitwiseType::XOR => "xor",
-					&BitwiseType::SHIFT(ref kind) => {
-						match kind {
-							&ShiftType::LEFT => "shl",
-							&ShiftType::RIGHT => "shr",
+			Instruction::Bitwise(ref kind, source, target, destination) => {
+				let string = match *kind {
+					BitwiseType::AND => "and",
+					BitwiseType::OR  => "or",
+					BitwiseType::XOR => "xor",
+					BitwiseType::SHIFT(ref kind) => {
+						match *kind {
+							ShiftType::LEFT => "shl",
+							ShiftType::RIGHT => "shr",
+							ShiftType::LOGICAL => "shru",
 						}
 					}
 				};
 				write!(f, "{} {} {} {}", string, source, target, destination)
 			},
-			&Instruction::Branch(ref kind, ref label) => {
-				let string: String = match kind {
-					&BranchType::UNCONDITIONAL => "jmp".to_string(),
-					&BranchType::NOTZERO(register) => format!("jnz {}", register),
-					&BranchType::ZERO(register) => format!("jz {}", register),
+			Instruction::Unary(ref kind, source, destination) => {
+				let string = match *kind {
+					UnaryType::NEG => "neg",
+					UnaryType::NOT => "not",
+					UnaryType::ABS => "abs",
+				};
+				write!(f, "{} {} {}", string, source, destination)
+			},
+			Instruction::Compare(ref kind, source, target, destination) => {
+				let string = match *kind {
+					ComparisonType::EQ => "seq",
+					ComparisonType::NE => "sne",
+					ComparisonType::LT => "slt",
+					ComparisonType::LE => "sle",
+					ComparisonType::GT => "sgt",
+					ComparisonType::GE => "sge",
+					ComparisonType::LTU => "sltu",
+					ComparisonType::LEU => "sleu",
+					ComparisonType::GTU => "sgtu",
+					ComparisonType::GEU => "sgeu",
+				};
+				write!(f, "{} {} {} {}", string, source, target, destination)
+			},
+			Instruction::Branch(ref kind, ref label) => {
+				let string: String = match *kind {
+					BranchType::UNCONDITIONAL => "jmp".to_string(),
+					BranchType::NOTZERO(register) => format!("jnz {}", register),
+					BranchType::ZERO(register) => format!("jz {}", register),
+					BranchType::COMPARE(ref kind, source, target) => {
+						let string = match *kind {
+							ComparisonType::EQ => "jeq",
+							ComparisonType::NE => "jne",
+							ComparisonType::LT => "jlt",
+							ComparisonType::LE => "jle",
+							ComparisonType::GT => "jgt",
+							ComparisonType::GE => "jge",
+							ComparisonType::LTU => "jltu",
+							ComparisonType::LEU => "jleu",
+							ComparisonType::GTU => "jgtu",
+							ComparisonType::GEU => "jgeu",
+						};
+						format!("{} {} {}", string, source, target)
+					},
+					BranchType::CALL => "call".to_string(),
 				};
 				write!(f, "{} {}", string, label)
 			},
-			&Instruction::Control(ref kind) => {
-				let string = match kind {
-					&ControlType::LBL(ref label) => format!("{}:", label),
+			Instruction::Control(ref kind) => {
+				let string = match *kind {
+					ControlType::LBL(ref label) => format!("{}:", label),
+					ControlType::DATA(address, ref text) => format!(".data {} \"{}\"", address, escape(text)),
 				};
 				write!(f, "{}", string)
 			}
-			&Instruction::Assignment(ref kind, register) => {
-				let string = match kind {
-					&AssignmentType::STR(value) => format!("str {}", value),
-					&AssignmentType::CPY(value) => format!("cpy {}", value),
+			Instruction::Assignment(ref kind, register) => {
+				let string = match *kind {
+					AssignmentType::STR(value) => format!("str {}", value),
+					AssignmentType::CPY(value) => format!("cpy {}", value),
 				};
 				 write!(f, "{} {}", string, register)
 			}
-			&Instruction::NOP => write!(f, "nop"), 
-			&Instruction::HLT => write!(f, "hlt"), 
+			Instruction::Memory(ref kind, register, address) => {
+				match *kind {
+					MemoryType::LOAD => write!(f, "ld {} {}", address, register),
+					MemoryType::STORE => write!(f, "st {} {}", register, address),
+				}
+			}
+			Instruction::Stack(ref kind, register) => {
+				let string = match *kind {
+					StackType::PUSH => "push",
+					StackType::POP => "pop",
+				};
+				write!(f, "{} {}", string, register)
+			}
+			Instruction::FloatArithmatic(ref kind, source, target, destination) => {
+				let string = match *kind {
+					FloatArithmaticType::ADD => "fadd",
+					FloatArithmaticType::SUB => "fsub",
+					FloatArithmaticType::MUL => "fmul",
+					FloatArithmaticType::DIV => "fdiv",
+				};
+				write!(f, "{} {} {} {}", string, source, target, destination)
+			}
+			Instruction::FloatUnary(ref kind, source, destination) => {
+				let string = match *kind {
+					FloatUnaryType::SQRT => "fsqrt",
+					FloatUnaryType::NEG => "fneg",
+					FloatUnaryType::ABS => "fabs",
+				};
+				write!(f, "{} {} {}", string, source, destination)
+			}
+			Instruction::FloatAssignment(ref kind, register) => {
+				let string = match *kind {
+					FloatAssignmentType::STR(value) => format!("fstr {:?}", value),
+					FloatAssignmentType::CPY(value) => format!("fcpy {}", value),
+				};
+				write!(f, "{} {}", string, register)
+			}
+			Instruction::FloatIO(ref kind, register) => {
+				let string = match *kind {
+					FloatIOType::OUT => "fout",
+					FloatIOType::OUTN => "foutn",
+				};
+				write!(f, "{} {}", string, register)
+			}
+			Instruction::Conversion(ref kind, register, float) => {
+				match *kind {
+					ConversionType::TOFLOAT => write!(f, "itof {} {}", register, float),
+					ConversionType::TOINT => write!(f, "ftoi {} {}", float, register),
+				}
+			}
+			Instruction::NOP => write!(f, "nop"), 
+			Instruction::HLT => write!(f, "hlt"), 
+			Instruction::RET => write!(f, "ret"), 
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Operand::Reg(register) => write!(f, "{}", register),
+			Operand::Imm(value) => write!(f, "{}", value),
+		}
+	}
+}
+
+impl fmt::Display for Address {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.offset {
+			0 => write!(f, "[{}]", self.base),
+			offset => write!(f, "[{} + {}]", self.base, offset),
 		}
 	}
 }
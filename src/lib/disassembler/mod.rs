@@ -1,12 +1,63 @@
 pub mod instructions;
 pub mod registers;
 
+use bytecode::{Object, BytecodeError};
+use instructions::{Instruction, ControlType};
+use vm::WordSize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Options {
+	/// Append the encoded bytes of each instruction after its offset.
+	pub hex: bool,
+}
+
+/// Disassembles an object back into source that reassembles to the same code.
+/// Each instruction is followed by a comment with its code offset, and
+/// optionally its encoding. Objects with 64-bit words start with a comment
+/// noting so, as they need `--word-size 64` to reassemble.
+pub fn disassemble(object: &Object, options: Options) -> Result<String, BytecodeError> {
+	let mut output = String::new();
+	if object.word_size == WordSize::Bits64 {
+		output.push_str("# 64-bit words\n");
+	}
+	for decoded in object.decode()? {
+		match decoded.instruction {
+			Instruction::Control(ControlType::LBL(_)) => {
+				output.push_str(&format!("{}\n", decoded.instruction));
+			},
+			ref instruction => {
+				let mut line = format!("\t{:<24}# {:04x}", instruction.to_string(), decoded.offset);
+				if options.hex {
+					for byte in &object.code[decoded.offset..decoded.offset + decoded.len] {
+						line.push_str(&format!(" {:02x}", byte));
+					}
+				}
+				output.push_str(&line);
+				output.push('\n');
+			}
+		}
+	}
+	Ok(output)
+}
 
 #[cfg(test)]
 mod tests {
 	use std::fmt::Write;
+	use super::*;
+	use bytecode::{self, Object};
+	use lexer::tokenize;
+	use parser::parse;
 	use instructions::Instruction;
-	use registers::Register;
+	use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BranchType, ControlType, AssignmentType, MemoryType, Operand, Address};
+	use instructions::{FloatAssignmentType, ConversionType};
+	use registers::{Register, FloatRegister};
+
+	fn program(source: &str) -> Vec<Instruction> {
+		parse(tokenize(source).unwrap()).unwrap()
+	}
+	fn display(program: &[Instruction]) -> String {
+		program.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("\n")
+	}
 	#[test]
 	fn nop() {
 		let instruction = Instruction::NOP;
@@ -14,77 +65,140 @@ mod tests {
 		write!(&mut string, "{}", instruction).unwrap();
 		assert_eq!(string, "nop")
 	}
-	// #[test]
-	// fn out() {
+	#[test]
+	fn out() {
 
-	// 	let instruction = Instruction::OUT(Register::RA);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "out ra")
-	// }
-	// #[test]
-	// fn add() {
-	// 	let instruction = Instruction::ADD(Register::RA, Register::RB, Register::RC);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "add ra rb rc")
-	// }
-	// #[test]
-	// fn sub() {
-	// 	let instruction = Instruction::SUB(Register::RA, Register::RB, Register::RC);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "sub ra rb rc")
-	// }
-	// #[test]
-	// fn mul() {
-	// 	let instruction = Instruction::MUL(Register::RA, Register::RB, Register::RC);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "mul ra rb rc")
-	// }
-	// #[test]
-	// fn div() {
-	// 	let instruction = Instruction::DIV(Register::RA, Register::RB, Register::RC);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "div ra rb rc")
-	// }
-	// #[test]
-	// fn str() {
-	// 	let instruction = Instruction::STR(6, Register::RA);
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "str 6 ra")
-	// }
-	// #[test]
-	// fn jmp() {
-	// 	let instruction = Instruction::JMP("end".to_string());
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "jmp end")
-	// }
-	// #[test]
-	// fn jz() {
-	// 	let instruction = Instruction::JZ(Register::RA, "end".to_string());
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "jz ra end")
-	// }
-	// #[test]
-	// fn jnz() {
-	// 	let instruction = Instruction::JNZ(Register::RA, "end".to_string());
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "jnz ra end")
-	// }
-	// #[test]
-	// fn lbl() {
-	// 	let instruction = Instruction::LBL("end".to_string());
-	// 	let mut string = String::new(); 
-	// 	write!(&mut string, "{}", instruction).unwrap();
-	// 	assert_eq!(string, "end:")
-	// }
+		let instruction = Instruction::IO(IOType::OUT, Register::RA);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "out ra")
+	}
+	#[test]
+	fn add() {
+		let instruction = Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "add ra rb rc")
+	}
+	#[test]
+	fn sub() {
+		let instruction = Instruction::Arithmatic(ArithmaticType::SUB, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "sub ra rb rc")
+	}
+	#[test]
+	fn mul() {
+		let instruction = Instruction::Arithmatic(ArithmaticType::MUL, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "mul ra rb rc")
+	}
+	#[test]
+	fn div() {
+		let instruction = Instruction::Arithmatic(ArithmaticType::DIV, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "div ra rb rc")
+	}
+	#[test]
+	fn str() {
+		let instruction = Instruction::Assignment(AssignmentType::STR(6), Register::RA);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "str 6 ra")
+	}
+	#[test]
+	fn immediate() {
+		let instruction = Instruction::Arithmatic(ArithmaticType::SUB, Operand::Reg(Register::RA), Operand::Imm(-1), Register::RA);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "sub ra -1 ra")
+	}
+	#[test]
+	fn neg() {
+		let instruction = Instruction::Unary(UnaryType::NEG, Register::RA, Register::RB);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "neg ra rb")
+	}
+	#[test]
+	fn slt() {
+		let instruction = Instruction::Compare(ComparisonType::LT, Register::RA, Register::RB, Register::RC);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "slt ra rb rc")
+	}
+	#[test]
+	fn fstr() {
+		let instruction = Instruction::FloatAssignment(FloatAssignmentType::STR(-2.0), FloatRegister::FB);
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "fstr -2.0 fb")
+	}
+	#[test]
+	fn ftoi() {
+		let instruction = Instruction::Conversion(ConversionType::TOINT, Register::RA, FloatRegister::from_index(9).unwrap());
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "ftoi f9 ra")
+	}
+	#[test]
+	fn jmp() {
+		let instruction = Instruction::Branch(BranchType::UNCONDITIONAL, "end".to_string());
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "jmp end")
+	}
+	#[test]
+	fn jz() {
+		let instruction = Instruction::Branch(BranchType::ZERO(Register::RA), "end".to_string());
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "jz ra end")
+	}
+	#[test]
+	fn jnz() {
+		let instruction = Instruction::Branch(BranchType::NOTZERO(Register::RA), "end".to_string());
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "jnz ra end")
+	}
+	#[test]
+	fn jlt() {
+		let instruction = Instruction::Branch(BranchType::COMPARE(ComparisonType::LTU, Register::RA, Register::RB), "end".to_string());
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "jltu ra rb end")
+	}
+	#[test]
+	fn ld() {
+		let instruction = Instruction::Memory(MemoryType::LOAD, Register::RB, Address { base: Register::RA, offset: 4 });
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "ld [ra + 4] rb")
+	}
+	#[test]
+	fn st() {
+		let instruction = Instruction::Memory(MemoryType::STORE, Register::RB, Address { base: Register::RA, offset: 0 });
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "st rb [ra]")
+	}
+	#[test]
+	fn lbl() {
+		let instruction = Instruction::Control(ControlType::LBL("end".to_string()));
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, "end:")
+	}
+	#[test]
+	fn data() {
+		let instruction = Instruction::Control(ControlType::DATA(8, "a \"b\"\n".to_string()));
+		let mut string = String::new(); 
+		write!(&mut string, "{}", instruction).unwrap();
+		assert_eq!(string, ".data 8 \"a \\\"b\\\"\\n\"")
+	}
 	#[test]
 	fn hlt() {
 		let instruction = Instruction::HLT;
@@ -92,4 +206,56 @@ mod tests {
 		write!(&mut string, "{}", instruction).unwrap();
 		assert_eq!(string, "hlt")
 	}
+	#[test]
+	fn round_trip_display() {
+		let sources = [
+			include_str!("../../../examples/factorial.vma"),
+			include_str!("../../../examples/fibonacci.vma"),
+			"main:\nnop\nin ra\nout rb\nadd ra rb rc\nsub ra rb rc\nmul ra rb rc\ndiv ra rb rc\nmax ra rb rc\nmin ra rb rc\nmod ra rb rc\n\
+			add ra 1 ra\nsub 0 rb rb\nand ra 0xff rb\nshl 1 rc rd\n\
+			neg ra rb\nnot rc rd\nabs re rf\nseq ra rb rc\nsne ra rb rc\nslt ra rb rc\nsle ra rb rc\nsgt ra rb rc\nsge ra rb rc\n\
+			sltu ra rb rc\nsleu ra rb rc\nsgtu ra rb rc\nsgeu ra rb rc\n\
+			and ra rb rc\nor ra rb rc\nxor ra rb rc\nshl ra rb rc\nshr ra rb rc\nshru ra rb rc\n\
+			jmp main\njz ra main\njnz rb main\n\
+			jeq ra rb main\njne ra rb main\njlt ra rb main\njle ra rb main\njgt ra rb main\njge ra rb main\n\
+			jltu ra rb main\njleu ra rb main\njgtu ra rb main\njgeu ra rb main\nstr -6 rc\ncpy rd re\n\
+			ld [ra] rb\nld [rc + 3] rd\nst re [rf -2]\n\
+			push ra\npop rb\ncall main\nret\n\
+			outn ra\noutc rb\nprints rc\n.data 64 \"tab\\t \\\"quoted\\\"\\n\"\nhlt",
+		];
+		for source in sources.iter() {
+			let parsed = program(source);
+			assert_eq!(program(&display(&parsed)), parsed);
+		}
+	}
+	#[test]
+	fn disassemble_listing() {
+		let object = Object::assemble(&program("main:\nstr 1 ra\nloop:\njnz ra loop\nhlt"), WordSize::Bits32).unwrap();
+		assert_eq!(disassemble(&object, Options::default()).unwrap(),
+			"main:\n\tstr 1 ra                # 0000\nl0006:\n\tjnz ra l0006            # 0006\n\thlt                     # 000c\n");
+		assert_eq!(disassemble(&object, Options { hex: true }).unwrap(),
+			"main:\n\tstr 1 ra                # 0000 50 00 00 00 00 00\nl0006:\n\tjnz ra l0006            # 0006 42 00 06 00 00 00\n\thlt                     # 000c 01\n");
+	}
+	#[test]
+	fn round_trip_bytecode() {
+		let sources = [include_str!("../../../examples/factorial.vma"), include_str!("../../../examples/fibonacci.vma"),
+			"main:\nld [ra] rb\nst rb [rc + -8]\ncall sub\nhlt\nsub:\npush ra\npop rb\nret",
+			"main:\nmod ra rb rc\nneg ra rb\nnot rc rd\nabs re rf\nshru ra rb rc\nseq ra rb rc\nsge rd re rf\nsltu ra rb rc\nhlt",
+			".alias counter r7\nmain:\ncpy r31 r254\nadd counter 1 counter\nld [r9 + 2] r6\nhlt",
+			"main:\nfstr 1.5 fa\nfstr -0.0 fb\nfstr 1e300 fc\nfstr 2.5e-10 fd\nfadd fa fb fc\nfsub fa fb fc\nfmul fa fb fc\nfdiv fa fb fc\n\
+			fsqrt fa fb\nfneg fc fd\nfabs fe ff\nfcpy fa f9\nfout fa\nfoutn fb\nitof ra fa\nftoi fb rb\nhlt",
+			"main:\nadd ra 1 ra\nsub 100 rb rc\nxor rd -1 rd\nshru re 4 rf\nhlt",
+			"main:\nloop:\njlt ra rb end\njgeu rc rd loop\njne re rf main\nend:\nhlt",
+			".data 0 \"héllo\\n\"\nmain:\nstr 0 ra\nprints ra\noutn rb\noutc rc\nhlt"];
+		for source in sources.iter() {
+			for &word_size in [WordSize::Bits32, WordSize::Bits64].iter() {
+				let bytes = bytecode::assemble(&program(source), word_size).unwrap();
+				let object = Object::from_bytes(&bytes).unwrap();
+				let text = disassemble(&object, Options { hex: true }).unwrap();
+				assert_eq!(text.starts_with("# 64-bit words\n"), word_size == WordSize::Bits64);
+				assert_eq!(program(&text), bytecode::load(&bytes).unwrap().0);
+				assert_eq!(bytecode::assemble(&program(&text), word_size).unwrap(), bytes);
+			}
+		}
+	}
 }
\ No newline at end of file
@@ -1,16 +1,21 @@
-use registers::Register;
+use registers::{Register, FloatRegister};
 use std::fmt;
 
 
 impl fmt::Display for Register {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
-            &Register::RA => write!(f, "ra"),
-            &Register::RB => write!(f, "rb"),
-            &Register::RC => write!(f, "rc"),
-            &Register::RD => write!(f, "rd"),
-            &Register::RE => write!(f, "re"),
-            &Register::RF => write!(f, "rf"),
-        }
-    }
-}
\ No newline at end of file
+		match self.name() {
+			Some(name) => write!(f, "{}", name),
+			None => write!(f, "r{}", self.index()),
+		}
+	}
+}
+
+impl fmt::Display for FloatRegister {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.name() {
+			Some(name) => write!(f, "{}", name),
+			None => write!(f, "f{}", self.index()),
+		}
+	}
+}
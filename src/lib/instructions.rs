@@ -1,24 +1,55 @@
-use registers::Register;
+use registers::{Register, FloatRegister};
 
 
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ArithmaticType {
 	ADD,
 	SUB,
 	MUL,
 	DIV,
 	MAX,
-	MIN
+	MIN,
+	/// Remainder, with the sign of the dividend.
+	MOD,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryType {
+	NEG,
+	NOT,
+	ABS,
+}
+
+/// A comparison between two registers, used by the set and compare-and-branch
+/// instructions. The `U` forms treat both registers as unsigned.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ComparisonType {
+	EQ,
+	NE,
+	LT,
+	LE,
+	GT,
+	GE,
+	LTU,
+	LEU,
+	GTU,
+	GEU,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum IOType {
 	IN,
 	OUT,
+	/// Prints a number without a trailing newline.
+	OUTN,
+	/// Prints a Unicode scalar value.
+	OUTC,
+	/// Prints the zero-terminated string at an address.
+	PRINTS,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BitwiseType {
 	AND,
 	OR,
@@ -26,42 +57,186 @@ pub enum BitwiseType {
 	SHIFT(ShiftType)
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ShiftType {
 	LEFT,
+	/// Arithmetic shift, filling with the sign bit.
 	RIGHT,
+	/// Logical shift right, filling with zeroes.
+	LOGICAL,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BranchType {
 	UNCONDITIONAL,
 	ZERO(Register),
-	NOTZERO(Register)
+	NOTZERO(Register),
+	/// Branches if the comparison between the two registers holds.
+	COMPARE(ComparisonType, Register, Register),
+	CALL,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ControlType {
-	LBL(String)
+	LBL(String),
+	/// Places a zero-terminated string in memory at an address before the
+	/// program starts.
+	DATA(i32, String),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum AssignmentType {
-	STR(i32),
+	STR(i64),
 	CPY(Register)
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StackType {
+	PUSH,
+	POP,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryType {
+	LOAD,
+	STORE,
+}
+
+/// Floating point arithmetic follows IEEE 754, so dividing by zero gives an
+/// infinity or NaN rather than an error.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FloatArithmaticType {
+	ADD,
+	SUB,
+	MUL,
+	DIV,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FloatUnaryType {
+	/// The square root, NaN for negative values.
+	SQRT,
+	NEG,
+	ABS,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FloatAssignmentType {
+	STR(f64),
+	CPY(FloatRegister),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FloatIOType {
+	OUT,
+	/// Prints without a trailing newline.
+	OUTN,
+}
+
+/// Moves a value between the integer and float register banks.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConversionType {
+	/// Converts an integer register into a float register.
+	TOFLOAT,
+	/// Truncates a float register towards zero into an integer register.
+	TOINT,
+}
+
+/// A source operand of an arithmetic or bitwise instruction, either a
+/// register or an immediate value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+	Reg(Register),
+	Imm(i64),
+}
+
+/// A memory operand, `[base + offset]`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Address {
+	pub base: Register,
+	pub offset: i32,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Instruction {
 	IO(IOType, Register), 
-	Arithmatic(ArithmaticType, Register, Register, Register),
-	Bitwise(BitwiseType, Register, Register, Register),
+	Arithmatic(ArithmaticType, Operand, Operand, Register),
+	Bitwise(BitwiseType, Operand, Operand, Register),
+	Unary(UnaryType, Register, Register),
+	Compare(ComparisonType, Register, Register, Register),
 	Branch(BranchType, String),
 	Control(ControlType),
 	Assignment(AssignmentType, Register),
+	Memory(MemoryType, Register, Address),
+	Stack(StackType, Register),
+	FloatArithmatic(FloatArithmaticType, FloatRegister, FloatRegister, FloatRegister),
+	FloatUnary(FloatUnaryType, FloatRegister, FloatRegister),
+	FloatAssignment(FloatAssignmentType, FloatRegister),
+	FloatIO(FloatIOType, FloatRegister),
+	Conversion(ConversionType, Register, FloatRegister),
 	NOP,
 	HLT,
+	RET,
 }
 
+impl Instruction {
+	/// Every register the instruction reads or writes.
+	pub fn registers(&self) -> Vec<Register> {
+		let operands = |operands: &[Operand]| operands.iter().filter_map(|operand| match *operand {
+			Operand::Reg(register) => Some(register),
+			Operand::Imm(_) => None,
+		}).collect::<Vec<_>>();
+		match *self {
+			Instruction::IO(_, register) | Instruction::Stack(_, register) => vec![register],
+			Instruction::Arithmatic(_, source, target, destination) | Instruction::Bitwise(_, source, target, destination) => {
+				let mut registers = operands(&[source, target]);
+				registers.push(destination);
+				registers
+			},
+			Instruction::Unary(_, source, destination) => vec![source, destination],
+			Instruction::Compare(_, source, target, destination) => vec![source, target, destination],
+			Instruction::Branch(ref kind, _) => match *kind {
+				BranchType::ZERO(register) | BranchType::NOTZERO(register) => vec![register],
+				BranchType::COMPARE(_, source, target) => vec![source, target],
+				BranchType::UNCONDITIONAL | BranchType::CALL => vec![],
+			},
+			Instruction::Assignment(ref kind, register) => match *kind {
+				AssignmentType::CPY(source) => vec![source, register],
+				AssignmentType::STR(_) => vec![register],
+			},
+			Instruction::Memory(_, register, address) => vec![register, address.base],
+			Instruction::Conversion(_, register, _) => vec![register],
+			Instruction::FloatArithmatic(..) | Instruction::FloatUnary(..) | Instruction::FloatAssignment(..) | Instruction::FloatIO(..) |
+			Instruction::Control(_) | Instruction::NOP | Instruction::HLT | Instruction::RET => vec![],
+		}
+	}
+	/// Every float register the instruction reads or writes.
+	pub fn float_registers(&self) -> Vec<FloatRegister> {
+		match *self {
+			Instruction::FloatArithmatic(_, source, target, destination) => vec![source, target, destination],
+			Instruction::FloatUnary(_, source, destination) => vec![source, destination],
+			Instruction::FloatAssignment(ref kind, register) => match *kind {
+				FloatAssignmentType::CPY(source) => vec![source, register],
+				FloatAssignmentType::STR(_) => vec![register],
+			},
+			Instruction::FloatIO(_, register) | Instruction::Conversion(_, _, register) => vec![register],
+			_ => vec![],
+		}
+	}
+	/// Every integer literal the instruction loads, excluding address offsets.
+	pub fn literals(&self) -> Vec<i64> {
+		match *self {
+			Instruction::Arithmatic(_, source, target, _) | Instruction::Bitwise(_, source, target, _) => {
+				[source, target].iter().filter_map(|operand| match *operand {
+					Operand::Imm(value) => Some(value),
+					Operand::Reg(_) => None,
+				}).collect()
+			},
+			Instruction::Assignment(AssignmentType::STR(value), _) => vec![value],
+			_ => vec![],
+		}
+	}
+}
 
 // impl ToString for Instruction {
 // 	fn to_string(&self) -> String {
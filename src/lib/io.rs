@@ -0,0 +1,227 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum InputError {
+	EndOfInput,
+	Malformed(String),
+	/// A value too large for the VM's word size.
+	OutOfRange(i64),
+	Io(String),
+}
+
+impl fmt::Display for InputError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			InputError::EndOfInput => write!(f, "end of input"),
+			InputError::Malformed(ref text) => write!(f, "malformed input `{}`, expected an integer", text),
+			InputError::OutOfRange(value) => write!(f, "input `{}` doesn't fit in a word", value),
+			InputError::Io(ref message) => write!(f, "failed to read input: {}", message),
+		}
+	}
+}
+
+/// A source of values for the `in` instruction.
+pub trait Input {
+	fn read(&mut self) -> Result<i64, InputError>;
+}
+
+/// A source of lines for `TextInput`.
+pub trait ReadLine {
+	fn read_line(&mut self, line: &mut String) -> io::Result<usize>;
+}
+
+impl<R: BufRead> ReadLine for R {
+	fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+		BufRead::read_line(self, line)
+	}
+}
+
+/// Reads stdin a line at a time through the handle shared with the rest of
+/// the process, so nothing past the current line is taken from other readers.
+pub struct StdinLines;
+
+impl ReadLine for StdinLines {
+	fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+		io::stdin().read_line(line)
+	}
+}
+
+/// Reads whitespace separated integers from text, such as stdin.
+pub struct TextInput<R> {
+	reader: R,
+	words: VecDeque<String>,
+}
+
+impl<R: ReadLine> TextInput<R> {
+	pub fn new(reader: R) -> TextInput<R> {
+		TextInput {
+			reader,
+			words: VecDeque::new()
+		}
+	}
+}
+
+impl TextInput<StdinLines> {
+	pub fn stdin() -> TextInput<StdinLines> {
+		TextInput::new(StdinLines)
+	}
+}
+
+impl<R: ReadLine> Input for TextInput<R> {
+	fn read(&mut self) -> Result<i64, InputError> {
+		while self.words.is_empty() {
+			let mut line = String::new();
+			match self.reader.read_line(&mut line) {
+				Ok(0) => return Err(InputError::EndOfInput),
+				Ok(_) => self.words.extend(line.split_whitespace().map(|word| word.to_string())),
+				Err(error) => return Err(InputError::Io(error.to_string())),
+			}
+		}
+		let word = self.words.pop_front().unwrap();
+		word.parse().map_err(|_| InputError::Malformed(word))
+	}
+}
+
+/// Supplies a fixed sequence of values, for tests and embedders.
+pub struct ScriptedInput {
+	values: VecDeque<i64>,
+}
+
+impl ScriptedInput {
+	pub fn new<I: IntoIterator<Item = i64>>(values: I) -> ScriptedInput {
+		ScriptedInput {
+			values: values.into_iter().collect()
+		}
+	}
+}
+
+impl Input for ScriptedInput {
+	fn read(&mut self) -> Result<i64, InputError> {
+		self.values.pop_front().ok_or(InputError::EndOfInput)
+	}
+}
+
+/// A sink for the output instructions.
+pub trait Output {
+	fn write(&mut self, text: &str) -> io::Result<()>;
+}
+
+/// Writes output to a `Write`, such as stdout, flushing after every write.
+pub struct TextOutput<W> {
+	writer: W,
+}
+
+impl<W: Write> TextOutput<W> {
+	pub fn new(writer: W) -> TextOutput<W> {
+		TextOutput {
+			writer
+		}
+	}
+}
+
+impl TextOutput<io::Stdout> {
+	pub fn stdout() -> TextOutput<io::Stdout> {
+		TextOutput::new(io::stdout())
+	}
+}
+
+impl<W: Write> Output for TextOutput<W> {
+	fn write(&mut self, text: &str) -> io::Result<()> {
+		self.writer.write_all(text.as_bytes())?;
+		self.writer.flush()
+	}
+}
+
+/// Collects output in memory. Clones share the same buffer, so a clone kept
+/// by the caller can read what the VM has written.
+#[derive(Debug, Clone, Default)]
+pub struct BufferOutput {
+	buffer: Arc<Mutex<String>>,
+}
+
+impl BufferOutput {
+	pub fn new() -> BufferOutput {
+		BufferOutput::default()
+	}
+	pub fn contents(&self) -> String {
+		self.buffer.lock().unwrap().clone()
+	}
+}
+
+impl Output for BufferOutput {
+	fn write(&mut self, text: &str) -> io::Result<()> {
+		self.buffer.lock().unwrap().push_str(text);
+		Ok(())
+	}
+}
+
+/// Passes output to a closure, e.g. to route it to a log or socket.
+pub struct CallbackOutput<F> {
+	callback: F,
+}
+
+impl<F: FnMut(&str) -> io::Result<()>> CallbackOutput<F> {
+	pub fn new(callback: F) -> CallbackOutput<F> {
+		CallbackOutput {
+			callback
+		}
+	}
+}
+
+impl<F: FnMut(&str) -> io::Result<()>> Output for CallbackOutput<F> {
+	fn write(&mut self, text: &str) -> io::Result<()> {
+		(self.callback)(text)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	#[test]
+	fn text_input() {
+		let mut input = TextInput::new(Cursor::new("1 -2\n\n  3\nfour\n"));
+		assert_eq!(input.read(), Ok(1));
+		assert_eq!(input.read(), Ok(-2));
+		assert_eq!(input.read(), Ok(3));
+		assert_eq!(input.read(), Err(InputError::Malformed("four".to_string())));
+		assert_eq!(input.read(), Err(InputError::EndOfInput));
+	}
+	#[test]
+	fn scripted_input() {
+		let mut input = ScriptedInput::new(vec![4, 2]);
+		assert_eq!(input.read(), Ok(4));
+		assert_eq!(input.read(), Ok(2));
+		assert_eq!(input.read(), Err(InputError::EndOfInput));
+	}
+	#[test]
+	fn text_output() {
+		let mut output = TextOutput::new(Vec::new());
+		output.write("1\n").unwrap();
+		output.write("2\n").unwrap();
+		assert_eq!(output.writer, b"1\n2\n");
+	}
+	#[test]
+	fn buffer_output() {
+		let buffer = BufferOutput::new();
+		let mut output = buffer.clone();
+		output.write("hello").unwrap();
+		assert_eq!(buffer.contents(), "hello");
+	}
+	#[test]
+	fn callback_output() {
+		let mut lines = vec![];
+		{
+			let mut output = CallbackOutput::new(|text: &str| {
+				lines.push(text.to_string());
+				Ok(())
+			});
+			output.write("a").unwrap();
+			output.write("b").unwrap();
+		}
+		assert_eq!(lines, vec!["a", "b"]);
+	}
+}
@@ -1,29 +1,61 @@
+use std::fmt;
+use std::num::IntErrorKind;
 use std::str::CharIndices;
 use std::iter::Peekable;
+use diagnostic::{Diagnostic, Span, Spanned};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
 	Identifier(String),
-	Value(i32),
+	Value(i64),
+	Float(f64),
+	Text(String),
+	Comma,
+	OpenBracket,
+	CloseBracket,
+	Plus,
+	Dot,
+	/// A malformed token, carrying a description of the problem.
+	Error(String),
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-	
-	let mut lexer = Lexer::new(input);
-	let mut output = vec![];
-
-	loop {
-
-		match lexer.next() {
-			Some(t) => {
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Token::Identifier(ref name) => write!(f, "{}", name),
+			Token::Value(value) => write!(f, "{}", value),
+			Token::Float(value) => write!(f, "{:?}", value),
+			Token::Text(ref text) => write!(f, "\"{}\"", escape(text)),
+			Token::Comma => write!(f, ","),
+			Token::OpenBracket => write!(f, "["),
+			Token::CloseBracket => write!(f, "]"),
+			Token::Plus => write!(f, "+"),
+			Token::Dot => write!(f, "."),
+			Token::Error(ref message) => write!(f, "{}", message),
+		}
+	}
+}
 
-				output.push(t);
+/// Tokenizes the whole input, failing with a diagnostic for every malformed
+/// token rather than returning a partial token stream.
+pub fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, Vec<Diagnostic>> {
+	
+	let lexer = Lexer::new(input);
+	let mut tokens = vec![];
+	let mut diagnostics = vec![];
 
-			}
-			None => break
+	for token in lexer {
+		if let Token::Error(ref message) = token.node {
+			diagnostics.push(Diagnostic::new(message.clone(), token.span));
+		} else {
+			tokens.push(token);
 		}
 	}
-	output
+	if diagnostics.is_empty() {
+		Ok(tokens)
+	} else {
+		Err(diagnostics)
+	}
 }
 
 pub struct Lexer<'a> {
@@ -35,9 +67,9 @@ pub struct Lexer<'a> {
 }
 
 impl<'a> Lexer<'a> {
-	fn new(input : &str) -> Lexer {
+	pub fn new(input : &str) -> Lexer<'_> {
 		Lexer {
-			input: input,
+			input,
 			iter: input.char_indices().peekable(),
 			pos: 0,
 			line: 1,
@@ -60,23 +92,15 @@ impl<'a> Lexer<'a> {
 				_ => self.column += 1
 
 			}
-			self.pos = i + 1;
+			self.pos = i + c.len_utf8();
 			return Some(c);
 		}
 		None
 	}
 	fn advance_while<P: Fn(char)-> bool>(&mut self, p: P) {
-		loop {
-
-			match self.peek() {
-				Some(c) => {
-
-					match p(c) {
-						false => break,
-						true => {}
-					}
-				}
-				None => break
+		while let Some(c) = self.peek() {
+			if !p(c) {
+				break;
 			}
 			self.advance();
 		}
@@ -87,17 +111,154 @@ impl<'a> Lexer<'a> {
 		}
 		None
 	}
+	fn peek_second(&self) -> Option<char> {
+		self.input[self.pos..].chars().nth(1)
+	}
 	fn handle_alphabetic(&mut self) -> Token {
 		let start = self.pos;
-		self.advance_while(is_alphabetic);
+		self.advance_while(|c| is_alphabetic(c) || is_numeric(c));
 		
 		Token::Identifier(self.input[start..self.pos].to_string())
 	}
 	fn handle_number(&mut self) -> Token {
 		let start = self.pos;
-		self.advance_while(is_numeric);
-		
-		Token::Value(self.input[start..self.pos].parse().unwrap())
+		let negative = self.peek() == Some('-');
+		if negative {
+			self.advance();
+		}
+		let (radix, name) = match (self.peek(), self.peek_second()) {
+			(Some('0'), Some('x')) | (Some('0'), Some('X')) => (16, "hexadecimal"),
+			(Some('0'), Some('b')) | (Some('0'), Some('B')) => (2, "binary"),
+			(Some('0'), Some('o')) | (Some('0'), Some('O')) => (8, "octal"),
+			_ => (10, "decimal"),
+		};
+		if radix != 10 {
+			self.advance();
+			self.advance();
+		}
+		let digits_start = self.pos;
+		if radix == 10 {
+			self.advance_while(|c| is_numeric(c) || c == '_');
+			let fraction = self.peek() == Some('.') && self.peek_second().is_some_and(is_numeric);
+			if fraction || matches!(self.peek(), Some('e') | Some('E')) {
+				return self.handle_float(start);
+			}
+		}
+		self.advance_while(|c| c.is_ascii_alphanumeric() || c == '_');
+
+		let literal = &self.input[start..self.pos];
+		let digits: String = self.input[digits_start..self.pos].chars().filter(|&c| c != '_').collect();
+		let magnitude = match u64::from_str_radix(&digits, radix) {
+			Ok(magnitude) => magnitude as i128,
+			Err(ref e) if *e.kind() == IntErrorKind::PosOverflow => {
+				return Token::Error(format!("literal `{}` does not fit in 64 bits", literal))
+			},
+			Err(_) => return Token::Error(format!("invalid {} literal `{}`", name, literal)),
+		};
+		let value = if negative { -magnitude } else { magnitude };
+		// Non-decimal literals may use all 64 bits, e.g. `0xFFFFFFFFFFFFFFFF` is -1.
+		let max = if radix == 10 { i64::MAX as i128 } else { u64::MAX as i128 };
+		if value < i64::MIN as i128 || value > max {
+			return Token::Error(format!("literal `{}` does not fit in 64 bits", literal));
+		}
+		Token::Value(value as i64)
+	}
+	/// Lexes the fraction and exponent of a float literal such as `-1.5e3`,
+	/// after its integer digits.
+	fn handle_float(&mut self, start: usize) -> Token {
+		if self.peek() == Some('.') {
+			self.advance();
+			self.advance_while(|c| is_numeric(c) || c == '_');
+		}
+		if let Some('e') | Some('E') = self.peek() {
+			self.advance();
+			if let Some('-') | Some('+') = self.peek() {
+				self.advance();
+			}
+		}
+		self.advance_while(|c| c.is_ascii_alphanumeric() || c == '_');
+
+		let literal = &self.input[start..self.pos];
+		let text: String = literal.chars().filter(|&c| c != '_').collect();
+		match text.parse::<f64>() {
+			Ok(value) if value.is_finite() => Token::Float(value),
+			Ok(_) => Token::Error(format!("literal `{}` does not fit in 64 bits", literal)),
+			Err(_) => Token::Error(format!("invalid float literal `{}`", literal)),
+		}
+	}
+	fn handle_escape(&mut self) -> Option<char> {
+		match self.advance() {
+			Some('n') => Some('\n'),
+			Some('t') => Some('\t'),
+			Some('r') => Some('\r'),
+			Some('0') => Some('\0'),
+			Some('\\') => Some('\\'),
+			Some('\'') => Some('\''),
+			Some('"') => Some('"'),
+			_ => None,
+		}
+	}
+	fn handle_character(&mut self) -> Token {
+		let start = self.pos;
+		self.advance();
+		let value = match self.advance() {
+			Some('\\') => self.handle_escape(),
+			Some('\'') | Some('\n') | None => None,
+			c => c,
+		};
+		match (value, self.peek()) {
+			(Some(c), Some('\'')) => {
+				self.advance();
+				Token::Value(c as i64)
+			},
+			_ => {
+				self.advance_while(|c| c != '\'' && c != '\n');
+				if self.peek() == Some('\'') {
+					self.advance();
+				}
+				Token::Error(format!("invalid character literal `{}`", &self.input[start..self.pos]))
+			}
+		}
+	}
+
+	fn handle_text(&mut self) -> Token {
+		let start = self.pos;
+		self.advance();
+		let mut text = String::new();
+		loop {
+			match self.peek() {
+				Some('"') => {
+					self.advance();
+					return Token::Text(text);
+				},
+				Some('\\') => {
+					self.advance();
+					match self.handle_escape() {
+						Some(c) => text.push(c),
+						None => {
+							self.advance_while(|c| c != '"' && c != '\n');
+							if self.peek() == Some('"') {
+								self.advance();
+							}
+							return Token::Error(format!("invalid escape in string literal `{}`", &self.input[start..self.pos]))
+						}
+					}
+				},
+				Some('\n') | None => return Token::Error("unterminated string literal".to_string()),
+				Some(c) => {
+					self.advance();
+					text.push(c);
+				}
+			}
+		}
+	}
+	fn handle_punctuation(&mut self, token: Token) -> Token {
+		self.advance();
+		token
+	}
+	fn handle_unexpected(&mut self, c: char) -> Token {
+		self.advance();
+		Token::Error(format!("unexpected character `{}`", c.escape_default()))
 	}
 
 	fn handle_whitespace(&mut self) {
@@ -110,12 +271,20 @@ impl<'a> Lexer<'a> {
 }
 
 impl<'a> Iterator for Lexer<'a> {
-	type Item = Token;
-	fn next(&mut self) -> Option<Token> {
-		
+	type Item = Spanned<Token>;
+	fn next(&mut self) -> Option<Spanned<Token>> {
+		let (start, line, column) = (self.pos, self.line, self.column);
 		let token = match self.peek() {
 			Some(c) if is_alphabetic(c) => self.handle_alphabetic(),
 			Some(c) if is_numeric(c) => self.handle_number(),
+			Some('-') if self.peek_second().is_some_and(is_numeric) => self.handle_number(),
+			Some('\'') => self.handle_character(),
+			Some('"') => self.handle_text(),
+			Some(',') => self.handle_punctuation(Token::Comma),
+			Some('[') => self.handle_punctuation(Token::OpenBracket),
+			Some(']') => self.handle_punctuation(Token::CloseBracket),
+			Some('+') => self.handle_punctuation(Token::Plus),
+			Some('.') => self.handle_punctuation(Token::Dot),
 			Some(c) if is_whitespace(c) => {
 				self.handle_whitespace(); 
 				match self.next() {
@@ -130,38 +299,43 @@ impl<'a> Iterator for Lexer<'a> {
 					None => return None,
 				}
 			},
+			Some(c) => self.handle_unexpected(c),
 			None => return None,
-			_ =>  return None,
 
 		};
-		Some(token)
-		
+		let len = self.input[start..self.pos].chars().count();
+		Some(Spanned::new(token, Span::new(line, column, len)))
 	}
 }
 
-fn is_numeric(c: char) -> bool {
-	match c {
-		'0'...'9' => true,
-		_ => false
+/// Escapes text so that it lexes back to the same string literal.
+pub fn escape(text: &str) -> String {
+	let mut escaped = String::new();
+	for c in text.chars() {
+		match c {
+			'\n' => escaped.push_str("\\n"),
+			'\t' => escaped.push_str("\\t"),
+			'\r' => escaped.push_str("\\r"),
+			'\0' => escaped.push_str("\\0"),
+			'\\' => escaped.push_str("\\\\"),
+			'"' => escaped.push_str("\\\""),
+			c => escaped.push(c),
+		}
 	}
+	escaped
+}
+
+fn is_numeric(c: char) -> bool {
+	c.is_ascii_digit()
 }
 fn is_alphabetic(c: char) -> bool {
-	match c {
-		'a' ... 'z' | 'A' ... 'Z' | ':' => true,
-		_ => false
-	}
+	matches!(c, 'a'..='z' | 'A'..='Z' | '_' | ':')
 }
 fn is_whitespace(c: char) -> bool {
-	match c {
-		' ' | '\t' | '\n' | '\r' => true,
-		_ => false
-	}
+	matches!(c, ' ' | '\t' | '\n' | '\r')
 }
 fn is_comment(c: char) -> bool {
-	match c {
-		'#' => true,
-		_ => false
-	}
+	c == '#'
 }
 
 #[cfg(test)]
@@ -173,14 +347,8 @@ mod tests {
 		let mut lexer = Lexer::new("test 14 @\ntesting\ntest\ttesting");
 		let mut output = String::new();
 
-		loop {
-			match lexer.advance() {
-				Some(c) => {
-					output.push(c);
-
-				},
-				None => break
-			}
+		while let Some(c) = lexer.advance() {
+			output.push(c);
 		}
 		assert_eq!(output, "test 14 @\ntesting\ntest\ttesting");
 		assert_eq!(lexer.line, 3);
@@ -188,17 +356,11 @@ mod tests {
 	}
 	#[test]
 	fn handle_comment(){
-		let mut lexer = Lexer::new("psh 7# pushes 7\n psh 8");
+		let lexer = Lexer::new("psh 7# pushes 7\n psh 8");
 		let mut  output = vec![];
 
-		loop {
-			match lexer.next() {
-				Some(c) => {
-					output.push(c);
-
-				},
-				None => break
-			}
+		for c in lexer {
+			output.push(c.node);
 		}
 		assert_eq!(output, vec![Token::Identifier("psh".to_string()), Token::Value(7), Token::Identifier("psh".to_string()), Token::Value(8)]);
 	}
@@ -222,15 +384,8 @@ mod tests {
 		let mut lexer = Lexer::new("The quick brown fox jumps over the 64 lazy dog");
 		let mut output = String::new();
 
-		loop {
-
-			match lexer.peek() {
-				Some(c) => {
-					output.push(c);
-
-				}
-				None => break
-			}
+		while let Some(c) = lexer.peek() {
+			output.push(c);
 			lexer.advance();
 		}
 		assert_eq!(output, "The quick brown fox jumps over the 64 lazy dog");
@@ -238,21 +393,85 @@ mod tests {
 	}
 	#[test]
 	fn iter(){
-		let mut lexer = Lexer::new("The 42\n 42");
+		let lexer = Lexer::new("The 42\n 42");
 		let mut output = vec![];
 
-		loop {
-			match lexer.next() {
-				Some(t) => {
-
-					output.push(t);
-
-				}
-				None => break
-			}
+		for t in lexer {
+			output.push(t.node);
 		}
 		assert_eq!(output, vec![Token::Identifier("The".to_string()), Token::Value(42), Token::Value(42)]);
 	}
+	#[test]
+	fn literals(){
+		let tokens: Vec<Token> = tokenize("-1 0x1F 0XfF 0b1010 -0b1 0o17 1_000_000 0xFFFF_FFFF 0xFFFF_FFFF_FFFF_FFFF -9223372036854775808 'a' '\\n' '\\''")
+			.unwrap().into_iter().map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Value(-1), Token::Value(31), Token::Value(255), Token::Value(10), Token::Value(-1),
+			Token::Value(15), Token::Value(1000000), Token::Value(0xFFFF_FFFF), Token::Value(-1), Token::Value(i64::MIN),
+			Token::Value(97), Token::Value(10), Token::Value(39)]);
+	}
+	#[test]
+	fn invalid_literals(){
+		let tokens: Vec<Token> = Lexer::new("99999999999999999999 9223372036854775808 0x1_0000_0000_0000_0000 0b102 12ab 0x 'ab' '\\q'")
+			.map(|t| t.node).collect();
+		assert_eq!(tokens, vec![
+			Token::Error("literal `99999999999999999999` does not fit in 64 bits".to_string()),
+			Token::Error("literal `9223372036854775808` does not fit in 64 bits".to_string()),
+			Token::Error("literal `0x1_0000_0000_0000_0000` does not fit in 64 bits".to_string()),
+			Token::Error("invalid binary literal `0b102`".to_string()),
+			Token::Error("invalid decimal literal `12ab`".to_string()),
+			Token::Error("invalid hexadecimal literal `0x`".to_string()),
+			Token::Error("invalid character literal `'ab'`".to_string()),
+			Token::Error("invalid character literal `'\\q'`".to_string()),
+		]);
+	}
+	#[test]
+	fn floats(){
+		let tokens: Vec<Token> = Lexer::new("1.5 -0.25 1e3 2.5E-3 1_000.000_1 1.e 1e 1e999 1.5.2").map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Float(1.5), Token::Float(-0.25), Token::Float(1000.0), Token::Float(0.0025), Token::Float(1000.0001),
+			Token::Value(1), Token::Dot, Token::Identifier("e".to_string()),
+			Token::Error("invalid float literal `1e`".to_string()),
+			Token::Error("literal `1e999` does not fit in 64 bits".to_string()),
+			Token::Float(1.5), Token::Dot, Token::Value(2)]);
+	}
+	#[test]
+	fn text(){
+		let tokens: Vec<Token> = tokenize("\"result: \" \"a\\tb\\n\\\"c\\\"\" \"\"").unwrap().into_iter().map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Text("result: ".to_string()), Token::Text("a\tb\n\"c\"".to_string()), Token::Text("".to_string())]);
+		assert_eq!(escape("a\tb\n\"c\""), "a\\tb\\n\\\"c\\\"");
+	}
+	#[test]
+	fn invalid_text(){
+		let errors = tokenize("\"abc\nout ra \"\\q\" hlt").unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("unterminated string literal", Span::new(1, 1, 4)),
+			Diagnostic::new("invalid escape in string literal `\"\\q\"`", Span::new(2, 8, 4))]);
+	}
+	#[test]
+	fn identifiers(){
+		let tokens: Vec<Token> = tokenize("loop_2: r31 _tmp").unwrap().into_iter().map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Identifier("loop_2:".to_string()), Token::Identifier("r31".to_string()), Token::Identifier("_tmp".to_string())]);
+	}
+	#[test]
+	fn punctuation(){
+		let tokens: Vec<Token> = tokenize("ld [ra + 4], rb .alias").unwrap().into_iter().map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Identifier("ld".to_string()), Token::OpenBracket, Token::Identifier("ra".to_string()), Token::Plus,
+			Token::Value(4), Token::CloseBracket, Token::Comma, Token::Identifier("rb".to_string()), Token::Dot, Token::Identifier("alias".to_string())]);
+	}
+	#[test]
+	fn unexpected_characters(){
+		let errors = tokenize("str 1 ra @\nout ra - hlt").unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("unexpected character `@`", Span::new(1, 10, 1)), Diagnostic::new("unexpected character `-`", Span::new(2, 8, 1))]);
+	}
+	#[test]
+	fn keeps_lexing_after_errors(){
+		let tokens: Vec<Token> = Lexer::new("a @ b").map(|t| t.node).collect();
+		assert_eq!(tokens, vec![Token::Identifier("a".to_string()), Token::Error("unexpected character `@`".to_string()), Token::Identifier("b".to_string())]);
+	}
+	#[test]
+	fn spans(){
+		let tokens = tokenize("main:\n\tstr 42 ra # comment\n").unwrap();
+		let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+		assert_eq!(spans, vec![Span::new(1, 1, 5), Span::new(2, 5, 3), Span::new(2, 9, 2), Span::new(2, 12, 2)]);
+	}
 	
 }
 
@@ -6,6 +6,10 @@ pub mod lexer;
 pub mod parser;
 pub mod instructions;
 pub mod registers;
+pub mod diagnostic;
+pub mod bytecode;
+pub mod io;
+pub mod analysis;
 
 pub mod disassembler;
 
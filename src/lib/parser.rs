@@ -1,157 +1,456 @@
+use std::fmt;
+use std::iter::Peekable;
 use std::vec;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use lexer::Token;
+use diagnostic::{Diagnostic, Span, Spanned};
 use instructions::Instruction;
-use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType};
-use registers::Register;
-pub type ParserResult<T> = Result<Option<T>, ParserError>;
+use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, StackType, Operand, Address};
+use instructions::{FloatArithmaticType, FloatUnaryType, FloatAssignmentType, FloatIOType, ConversionType};
+use registers::{Register, FloatRegister};
+pub type ParserResult<T> = Result<T, Spanned<ParserError>>;
 
-pub fn parse(input: Vec<Token>) -> Vec<Instruction> {
-	let mut parser = Parser::new(input);
-	let mut program = vec![];
-	while let Some(i) = parser.next() {
-		program.push(i)
-	}
-	program
+/// Parses a token stream, collecting a diagnostic for every malformed
+/// instruction rather than stopping at the first.
+pub fn parse(input: Vec<Spanned<Token>>) -> Result<Vec<Instruction>, Vec<Diagnostic>> {
+	parse_spanned(input).map(|program| program.into_iter().map(|i| i.node).collect())
+}
+/// Parses a token stream like `parse`, keeping the span of each instruction's
+/// first token so tools can map instructions back to the source.
+pub fn parse_spanned(input: Vec<Spanned<Token>>) -> Result<Vec<Spanned<Instruction>>, Vec<Diagnostic>> {
+	Parser::new(input).parse_all()
+}
+/// Parses a token stream like `parse`, starting with the aliases defined by
+/// earlier input. If it parses, the aliases it defines are added to them.
+pub fn parse_with(input: Vec<Spanned<Token>>, aliases: &mut Aliases) -> Result<Vec<Instruction>, Vec<Diagnostic>> {
+	let mut parser = Parser { aliases: aliases.clone(), ..Parser::new(input) };
+	let program = parser.parse_all()?;
+	*aliases = parser.aliases;
+	Ok(program.into_iter().map(|i| i.node).collect())
+}
+
+/// Register names defined with `.alias`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Aliases {
+	registers: HashMap<String, Register>,
+	float_registers: HashMap<String, FloatRegister>,
 }
+
 pub struct Parser {
-	iter: vec::IntoIter<Token>
+	iter: Peekable<vec::IntoIter<Spanned<Token>>>,
+	line: u32,
+	/// The first token of the instruction being parsed.
+	start: Span,
+	last: Span,
+	operands: usize,
+	aliases: Aliases,
 }
 
 #[derive(Debug,PartialEq)]
 pub enum ParserError {
-	InvalidInstruction,
-	InvalidArgument,
+	InvalidInstruction(String),
+	InvalidRegister(String),
+	InvalidToken(String),
+	InvalidDirective(String),
+	InvalidAlias(String),
+	Expected(&'static str, Option<Token>),
+}
+
+impl fmt::Display for ParserError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ParserError::InvalidInstruction(ref name) => write!(f, "unknown instruction `{}`", name),
+			ParserError::InvalidRegister(ref name) => write!(f, "unknown register `{}`", name),
+			ParserError::InvalidToken(ref message) => write!(f, "{}", message),
+			ParserError::InvalidDirective(ref name) => write!(f, "unknown directive `.{}`", name),
+			ParserError::InvalidAlias(ref name) => write!(f, "`{}` is already a register name", name),
+			ParserError::Expected(expected, Some(ref found)) => write!(f, "expected {}, found `{}`", expected, found),
+			ParserError::Expected(expected, None) => write!(f, "expected {}, found end of line", expected),
+		}
+	}
 }
 
 impl Parser {
-	fn new(input: Vec<Token>) -> Parser {
+	fn new(input: Vec<Spanned<Token>>) -> Parser {
 		Parser {
-			iter: input.into_iter()
+			iter: input.into_iter().peekable(),
+			line: 1,
+			start: Span::new(1, 1, 0),
+			last: Span::new(1, 1, 0),
+			operands: 0,
+			aliases: Aliases::default(),
 		}
 	}
-	fn advance(&mut self) -> Option<Token> {
-		if let Some(t) = self.iter.next() {
-			return Some(t);
+	/// Collects every instruction, or a diagnostic for every malformed one.
+	fn parse_all(&mut self) -> Result<Vec<Spanned<Instruction>>, Vec<Diagnostic>> {
+		let mut program = vec![];
+		let mut diagnostics = vec![];
+		for result in self {
+			match result {
+				Ok(i) => program.push(i),
+				Err(d) => diagnostics.push(d),
+			}
+		}
+		if diagnostics.is_empty() {
+			Ok(program)
+		} else {
+			Err(diagnostics)
 		}
-		None
 	}
-	fn take_register(&mut self) -> Option<Register> {
-		match self.advance().unwrap() {
-			Token::Identifier(r) => Some(Register::from(r)),
-			_ => return None
+	fn advance(&mut self) -> Option<Spanned<Token>> {
+		let token = self.iter.next();
+		if let Some(ref t) = token {
+			self.last = t.span;
 		}
+		token
 	}
-	fn take_value(&mut self) -> Option<i32> {
-		match self.advance().unwrap() {
-			Token::Value(i) => Some(i),
-			_ => return None
+	/// Takes the next token only if it is on the same line as the instruction
+	/// being parsed, so a missing argument doesn't swallow the next instruction.
+	/// Operands may optionally be separated by commas.
+	fn take_argument(&mut self, expected: &'static str) -> ParserResult<Spanned<Token>> {
+		let line = self.line;
+		if self.operands > 0 {
+			if let Some(comma) = self.iter.next_if(|t| t.span.line == line && t.node == Token::Comma) {
+				self.last = comma.span;
+			}
+		}
+		self.operands += 1;
+		match self.iter.next_if(|t| t.span.line == line) {
+			Some(Spanned { node: Token::Error(message), span }) => {
+				self.last = span;
+				Err(Spanned::new(ParserError::InvalidToken(message), span))
+			},
+			Some(t) => {
+				self.last = t.span;
+				Ok(t)
+			},
+			None => Err(Spanned::new(ParserError::Expected(expected, None), self.last.after()))
 		}
 	}
-
-	fn handle_io(&mut self, kind: IOType) -> ParserResult<Instruction> {
-		match self.take_register() {
-			Some(register) => Ok(Some(Instruction::IO(kind, register))),
-			None => return Err(ParserError::InvalidArgument)
+	fn skip_line(&mut self) {
+		let line = self.line;
+		while self.iter.next_if(|t| t.span.line == line).is_some() {}
+	}
+	/// Looks up a register by its alias or name.
+	fn register(&self, name: &str) -> Option<Register> {
+		self.aliases.registers.get(name).cloned().or_else(|| Register::from_name(name))
+	}
+	fn take_register(&mut self) -> ParserResult<Register> {
+		let token = self.take_argument("register")?;
+		match token.node {
+			Token::Identifier(r) => {
+				self.register(&r).ok_or(Spanned::new(ParserError::InvalidRegister(r), token.span))
+			},
+			t => Err(Spanned::new(ParserError::Expected("register", Some(t)), token.span))
 		}
 	}
-	fn handle_arithmatic(&mut self, kind: ArithmaticType) -> ParserResult<Instruction> {
-		match (self.take_register(), self.take_register(), self.take_register()) {
-			(Some(source), Some(target), Some(destination)) => {
-				Ok(Some(Instruction::Arithmatic(kind, source, target, destination)))
+	/// Takes a register or an immediate value.
+	fn take_operand(&mut self) -> ParserResult<Operand> {
+		let token = self.take_argument("register or value")?;
+		match token.node {
+			Token::Identifier(r) => {
+				self.register(&r).map(Operand::Reg).ok_or(Spanned::new(ParserError::InvalidRegister(r), token.span))
 			},
-			_ => return Err(ParserError::InvalidArgument)
+			Token::Value(i) => Ok(Operand::Imm(i)),
+			t => Err(Spanned::new(ParserError::Expected("register or value", Some(t)), token.span))
 		}
 	}
-	fn handle_bitwise(&mut self, kind: BitwiseType) -> ParserResult<Instruction> {
-		match (self.take_register(), self.take_register(), self.take_register()) {
-			(Some(source), Some(target), Some(destination)) => {
-				Ok(Some(Instruction::Bitwise(kind, source, target, destination)))
+	fn float_register(&self, name: &str) -> Option<FloatRegister> {
+		self.aliases.float_registers.get(name).cloned().or_else(|| FloatRegister::from_name(name))
+	}
+	fn take_float_register(&mut self) -> ParserResult<FloatRegister> {
+		let token = self.take_argument("float register")?;
+		match token.node {
+			Token::Identifier(r) => {
+				self.float_register(&r).ok_or(Spanned::new(ParserError::InvalidRegister(r), token.span))
 			},
-			_ => return Err(ParserError::InvalidArgument)
+			t => Err(Spanned::new(ParserError::Expected("float register", Some(t)), token.span))
 		}
 	}
-	fn handle_branch(&mut self, kind: BranchType) -> ParserResult<Instruction> {
-		let label = match self.advance().unwrap() {
-			Token::Identifier(r) => r,
-			_ => return Err(ParserError::InvalidArgument)
+	/// Takes a float value, also accepting integer literals.
+	fn take_float(&mut self) -> ParserResult<f64> {
+		let token = self.take_argument("float value")?;
+		match token.node {
+			Token::Float(value) => Ok(value),
+			Token::Value(value) => Ok(value as f64),
+			t => Err(Spanned::new(ParserError::Expected("float value", Some(t)), token.span))
+		}
+	}
+	fn take_value(&mut self) -> ParserResult<i64> {
+		let token = self.take_argument("value")?;
+		match token.node {
+			Token::Value(i) => Ok(i),
+			t => Err(Spanned::new(ParserError::Expected("value", Some(t)), token.span))
+		}
+	}
+	/// Takes a value that must fit in 32 bits, such as an address.
+	fn take_small_value(&mut self) -> ParserResult<i32> {
+		let value = self.take_value()?;
+		small_value(value, self.last)
+	}
+	fn take_text(&mut self) -> ParserResult<String> {
+		let token = self.take_argument("string")?;
+		match token.node {
+			Token::Text(text) => Ok(text),
+			t => Err(Spanned::new(ParserError::Expected("string", Some(t)), token.span))
+		}
+	}
+	fn expect(&mut self, expected: Token, name: &'static str) -> ParserResult<()> {
+		let token = self.take_argument(name)?;
+		if token.node == expected {
+			Ok(())
+		} else {
+			Err(Spanned::new(ParserError::Expected(name, Some(token.node)), token.span))
+		}
+	}
+	/// Takes a memory operand: `[base]`, `[base + offset]` or `[base -offset]`.
+	fn take_address(&mut self) -> ParserResult<Address> {
+		self.expect(Token::OpenBracket, "`[`")?;
+		let base = self.take_register()?;
+		let token = self.take_argument("`]`")?;
+		let offset = match token.node {
+			Token::CloseBracket => return Ok(Address { base, offset: 0 }),
+			Token::Plus => self.take_small_value()?,
+			Token::Value(offset) if offset < 0 => small_value(offset, token.span)?,
+			t => return Err(Spanned::new(ParserError::Expected("`+` or `]`", Some(t)), token.span))
 		};
-		Ok(Some(Instruction::Branch(kind, label)))
+		self.expect(Token::CloseBracket, "`]`")?;
+		Ok(Address { base, offset })
 	}
-	fn handle_control(&mut self, kind: ControlType) -> ParserResult<Instruction> {
-		Ok(Some(Instruction::Control(kind)))
+
+	fn handle_io(&mut self, kind: IOType) -> ParserResult<Instruction> {
+		let register = self.take_register()?;
+		Ok(Instruction::IO(kind, register))
 	}
-	fn handle_assignment(&mut self, kind: AssignmentType) -> ParserResult<Instruction> {
-		match self.take_register() {
-			Some(register) => Ok(Some(Instruction::Assignment(kind, register))),
-			None => return Err(ParserError::InvalidArgument)
+	fn handle_arithmatic(&mut self, kind: ArithmaticType) -> ParserResult<Instruction> {
+		let (source, target, destination) = (self.take_operand()?, self.take_operand()?, self.take_register()?);
+		Ok(Instruction::Arithmatic(kind, source, target, destination))
+	}
+	fn handle_bitwise(&mut self, kind: BitwiseType) -> ParserResult<Instruction> {
+		let (source, target, destination) = (self.take_operand()?, self.take_operand()?, self.take_register()?);
+		Ok(Instruction::Bitwise(kind, source, target, destination))
+	}
+	fn handle_unary(&mut self, kind: UnaryType) -> ParserResult<Instruction> {
+		let (source, destination) = (self.take_register()?, self.take_register()?);
+		Ok(Instruction::Unary(kind, source, destination))
+	}
+	fn handle_compare(&mut self, kind: ComparisonType) -> ParserResult<Instruction> {
+		let (source, target, destination) = (self.take_register()?, self.take_register()?, self.take_register()?);
+		Ok(Instruction::Compare(kind, source, target, destination))
+	}
+	fn handle_branch(&mut self, kind: BranchType) -> ParserResult<Instruction> {
+		let token = self.take_argument("label")?;
+		match token.node {
+			Token::Identifier(label) => Ok(Instruction::Branch(kind, label)),
+			t => Err(Spanned::new(ParserError::Expected("label", Some(t)), token.span))
 		}
 	}
-	fn next_instruction(&mut self) -> ParserResult<Instruction> {
-		let mut result = Ok(None);
-		if let Some(t) = self.advance() {
-			if let Token::Identifier(mut i) = t {
-				match i.as_ref() {
-					"nop" => result = Ok(Some(Instruction::NOP)),
-					"hlt" => result = Ok(Some(Instruction::HLT)),
-
-					"out" => result = self.handle_io(IOType::OUT),
-					"in" => result =  self.handle_io(IOType::IN),
-
-					"add" => result = self.handle_arithmatic(ArithmaticType::ADD),
-					"sub" => result = self.handle_arithmatic(ArithmaticType::SUB),
-					"mul" => result = self.handle_arithmatic(ArithmaticType::MUL),
-					"div" => result = self.handle_arithmatic(ArithmaticType::DIV),
-					"max" => result = self.handle_arithmatic(ArithmaticType::MAX),
-					"min" => result = self.handle_arithmatic(ArithmaticType::MIN),
-
-					"and" => result = self.handle_bitwise(BitwiseType::AND),
-					"or"  => result = self.handle_bitwise(BitwiseType::OR),
-					"xor" => result = self.handle_bitwise(BitwiseType::XOR),
-					"shr" => result = self.handle_bitwise(BitwiseType::SHIFT(ShiftType::RIGHT)),
-					"shl" => result = self.handle_bitwise(BitwiseType::SHIFT(ShiftType::LEFT)),
-
-					"jmp" => result = self.handle_branch(BranchType::UNCONDITIONAL),
-					"jz" => {
-						match self.take_register() {
-							Some(register) => result = self.handle_branch(BranchType::ZERO(register)),
-							None => return Err(ParserError::InvalidArgument)
-						}
-					},
-					"jnz" => {
-						match self.take_register() {
-							Some(register) => result = self.handle_branch(BranchType::NOTZERO(register)),
-							None => return Err(ParserError::InvalidArgument)
-						}
-					},
-					"str" => {
-						match self.take_value() {
-							Some(value) => result = self.handle_assignment(AssignmentType::STR(value)),
-							None => return Err(ParserError::InvalidArgument)
-						}
+	fn handle_compare_branch(&mut self, kind: ComparisonType) -> ParserResult<Instruction> {
+		let (source, target) = (self.take_register()?, self.take_register()?);
+		self.handle_branch(BranchType::COMPARE(kind, source, target))
+	}
+	fn handle_float_arithmatic(&mut self, kind: FloatArithmaticType) -> ParserResult<Instruction> {
+		let (source, target, destination) = (self.take_float_register()?, self.take_float_register()?, self.take_float_register()?);
+		Ok(Instruction::FloatArithmatic(kind, source, target, destination))
+	}
+	fn handle_float_unary(&mut self, kind: FloatUnaryType) -> ParserResult<Instruction> {
+		let (source, destination) = (self.take_float_register()?, self.take_float_register()?);
+		Ok(Instruction::FloatUnary(kind, source, destination))
+	}
+	fn handle_float_assignment(&mut self, kind: FloatAssignmentType) -> ParserResult<Instruction> {
+		let register = self.take_float_register()?;
+		Ok(Instruction::FloatAssignment(kind, register))
+	}
+	fn handle_float_io(&mut self, kind: FloatIOType) -> ParserResult<Instruction> {
+		let register = self.take_float_register()?;
+		Ok(Instruction::FloatIO(kind, register))
+	}
+	fn handle_conversion(&mut self, kind: ConversionType) -> ParserResult<Instruction> {
+		match kind {
+			ConversionType::TOFLOAT => {
+				let (register, float) = (self.take_register()?, self.take_float_register()?);
+				Ok(Instruction::Conversion(kind, register, float))
+			},
+			ConversionType::TOINT => {
+				let (float, register) = (self.take_float_register()?, self.take_register()?);
+				Ok(Instruction::Conversion(kind, register, float))
+			},
+		}
+	}
+	fn handle_control(&mut self, kind: ControlType) -> ParserResult<Instruction> {
+		Ok(Instruction::Control(kind))
+	}
+	fn handle_stack(&mut self, kind: StackType) -> ParserResult<Instruction> {
+		let register = self.take_register()?;
+		Ok(Instruction::Stack(kind, register))
+	}
+	/// Parses a directive, which may only affect the parser and produce no
+	/// instruction.
+	fn handle_directive(&mut self) -> ParserResult<Option<Instruction>> {
+		let token = self.take_argument("directive")?;
+		match token.node {
+			Token::Identifier(ref name) if name == "data" => {
+				let address = self.take_small_value()?;
+				let text = self.take_text()?;
+				Ok(Some(Instruction::Control(ControlType::DATA(address, text))))
+			},
+			Token::Identifier(ref name) if name == "alias" => {
+				let token = self.take_argument("alias name")?;
+				let alias = match token.node {
+					Token::Identifier(ref alias) if Register::from_name(alias).is_some() || FloatRegister::from_name(alias).is_some() => {
+						return Err(Spanned::new(ParserError::InvalidAlias(alias.clone()), token.span))
 					},
-					"cpy" => {
-						match self.take_register() {
-							Some(register) => result = self.handle_assignment(AssignmentType::CPY(register)),
-							None => return Err(ParserError::InvalidArgument)
-						}
+					Token::Identifier(alias) => alias,
+					t => return Err(Spanned::new(ParserError::Expected("alias name", Some(t)), token.span)),
+				};
+				let token = self.take_argument("register")?;
+				match token.node {
+					Token::Identifier(ref name) if self.float_register(name).is_some() => {
+						let register = self.float_register(name).unwrap();
+						self.aliases.float_registers.insert(alias, register);
 					},
-					_ if i.chars().last().unwrap() == ':' => {
-						i.pop().unwrap();
-						result = self.handle_control(ControlType::LBL(i))
+					Token::Identifier(name) => {
+						let register = self.register(&name).ok_or(Spanned::new(ParserError::InvalidRegister(name), token.span))?;
+						self.aliases.registers.insert(alias, register);
 					},
-					_ => return Err(ParserError::InvalidInstruction)
+					t => return Err(Spanned::new(ParserError::Expected("register", Some(t)), token.span)),
 				}
-			}
+				Ok(None)
+			},
+			Token::Identifier(name) => Err(Spanned::new(ParserError::InvalidDirective(name), token.span)),
+			t => Err(Spanned::new(ParserError::Expected("directive", Some(t)), token.span))
 		}
-		result
 	}
+	fn handle_assignment(&mut self, kind: AssignmentType) -> ParserResult<Instruction> {
+		let register = self.take_register()?;
+		Ok(Instruction::Assignment(kind, register))
+	}
+	fn handle_load(&mut self) -> ParserResult<Instruction> {
+		let address = self.take_address()?;
+		let register = self.take_register()?;
+		Ok(Instruction::Memory(MemoryType::LOAD, register, address))
+	}
+	fn handle_store(&mut self) -> ParserResult<Instruction> {
+		let register = self.take_register()?;
+		let address = self.take_address()?;
+		Ok(Instruction::Memory(MemoryType::STORE, register, address))
+	}
+	fn next_instruction(&mut self) -> Option<ParserResult<Instruction>> {
+		let token = self.advance()?;
+		self.line = token.span.line;
+		self.start = token.span;
+		self.operands = 0;
+		let result = match token.node {
+			Token::Identifier(mut i) => {
+				match i.as_ref() {
+					"nop" => Ok(Instruction::NOP),
+					"hlt" => Ok(Instruction::HLT),
+					"ret" => Ok(Instruction::RET),
+
+					"out" => self.handle_io(IOType::OUT),
+					"in" => self.handle_io(IOType::IN),
+					"outn" => self.handle_io(IOType::OUTN),
+					"outc" => self.handle_io(IOType::OUTC),
+					"prints" => self.handle_io(IOType::PRINTS),
+
+					"add" => self.handle_arithmatic(ArithmaticType::ADD),
+					"sub" => self.handle_arithmatic(ArithmaticType::SUB),
+					"mul" => self.handle_arithmatic(ArithmaticType::MUL),
+					"div" => self.handle_arithmatic(ArithmaticType::DIV),
+					"max" => self.handle_arithmatic(ArithmaticType::MAX),
+					"min" => self.handle_arithmatic(ArithmaticType::MIN),
+					"mod" => self.handle_arithmatic(ArithmaticType::MOD),
+
+					"neg" => self.handle_unary(UnaryType::NEG),
+					"not" => self.handle_unary(UnaryType::NOT),
+					"abs" => self.handle_unary(UnaryType::ABS),
+
+					"seq" => self.handle_compare(ComparisonType::EQ),
+					"sne" => self.handle_compare(ComparisonType::NE),
+					"slt" => self.handle_compare(ComparisonType::LT),
+					"sle" => self.handle_compare(ComparisonType::LE),
+					"sgt" => self.handle_compare(ComparisonType::GT),
+					"sge" => self.handle_compare(ComparisonType::GE),
+					"sltu" => self.handle_compare(ComparisonType::LTU),
+					"sleu" => self.handle_compare(ComparisonType::LEU),
+					"sgtu" => self.handle_compare(ComparisonType::GTU),
+					"sgeu" => self.handle_compare(ComparisonType::GEU),
+
+					"and" => self.handle_bitwise(BitwiseType::AND),
+					"or"  => self.handle_bitwise(BitwiseType::OR),
+					"xor" => self.handle_bitwise(BitwiseType::XOR),
+					"shr" => self.handle_bitwise(BitwiseType::SHIFT(ShiftType::RIGHT)),
+					"shl" => self.handle_bitwise(BitwiseType::SHIFT(ShiftType::LEFT)),
+					"shru" => self.handle_bitwise(BitwiseType::SHIFT(ShiftType::LOGICAL)),
+
+					"jmp" => self.handle_branch(BranchType::UNCONDITIONAL),
+					"jz" => self.take_register().and_then(|register| self.handle_branch(BranchType::ZERO(register))),
+					"jnz" => self.take_register().and_then(|register| self.handle_branch(BranchType::NOTZERO(register))),
+					"jeq" => self.handle_compare_branch(ComparisonType::EQ),
+					"jne" => self.handle_compare_branch(ComparisonType::NE),
+					"jlt" => self.handle_compare_branch(ComparisonType::LT),
+					"jle" => self.handle_compare_branch(ComparisonType::LE),
+					"jgt" => self.handle_compare_branch(ComparisonType::GT),
+					"jge" => self.handle_compare_branch(ComparisonType::GE),
+					"jltu" => self.handle_compare_branch(ComparisonType::LTU),
+					"jleu" => self.handle_compare_branch(ComparisonType::LEU),
+					"jgtu" => self.handle_compare_branch(ComparisonType::GTU),
+					"jgeu" => self.handle_compare_branch(ComparisonType::GEU),
+					"call" => self.handle_branch(BranchType::CALL),
+					"str" => self.take_value().and_then(|value| self.handle_assignment(AssignmentType::STR(value))),
+					"cpy" => self.take_register().and_then(|register| self.handle_assignment(AssignmentType::CPY(register))),
+
+					"ld" => self.handle_load(),
+					"st" => self.handle_store(),
+
+					"push" => self.handle_stack(StackType::PUSH),
+					"pop" => self.handle_stack(StackType::POP),
+
+					"fadd" => self.handle_float_arithmatic(FloatArithmaticType::ADD),
+					"fsub" => self.handle_float_arithmatic(FloatArithmaticType::SUB),
+					"fmul" => self.handle_float_arithmatic(FloatArithmaticType::MUL),
+					"fdiv" => self.handle_float_arithmatic(FloatArithmaticType::DIV),
+					"fsqrt" => self.handle_float_unary(FloatUnaryType::SQRT),
+					"fneg" => self.handle_float_unary(FloatUnaryType::NEG),
+					"fabs" => self.handle_float_unary(FloatUnaryType::ABS),
+					"fstr" => self.take_float().and_then(|value| self.handle_float_assignment(FloatAssignmentType::STR(value))),
+					"fcpy" => self.take_float_register().and_then(|register| self.handle_float_assignment(FloatAssignmentType::CPY(register))),
+					"fout" => self.handle_float_io(FloatIOType::OUT),
+					"foutn" => self.handle_float_io(FloatIOType::OUTN),
+					"itof" => self.handle_conversion(ConversionType::TOFLOAT),
+					"ftoi" => self.handle_conversion(ConversionType::TOINT),
+					_ if i.ends_with(':') => {
+						i.pop();
+						self.handle_control(ControlType::LBL(i))
+					},
+					_ => Err(Spanned::new(ParserError::InvalidInstruction(i), token.span))
+				}
+			},
+			Token::Dot => match self.handle_directive() {
+				Ok(Some(instruction)) => Ok(instruction),
+				Ok(None) => return self.next_instruction(),
+				Err(e) => Err(e),
+			},
+			Token::Error(message) => Err(Spanned::new(ParserError::InvalidToken(message), token.span)),
+			t => Err(Spanned::new(ParserError::Expected("instruction", Some(t)), token.span))
+		};
+		Some(result)
+	}
+}
+fn small_value(value: i64, span: Span) -> ParserResult<i32> {
+	i32::try_from(value).map_err(|_| Spanned::new(ParserError::InvalidToken(format!("literal `{}` does not fit in 32 bits", value)), span))
 }
+
 impl Iterator for Parser {
-	type Item = Instruction;
-	fn next(&mut self) -> Option<Instruction> {
-		match self.next_instruction() {
-			Ok(i) => i,
-			Err(e) => panic!("ParserError: {:?}", e)
+	type Item = Result<Spanned<Instruction>, Diagnostic>;
+	fn next(&mut self) -> Option<Result<Spanned<Instruction>, Diagnostic>> {
+		match self.next_instruction()? {
+			Ok(i) => Some(Ok(Spanned::new(i, self.start))),
+			Err(e) => {
+				self.skip_line();
+				Some(Err(Diagnostic::new(e.node.to_string(), e.span)))
+			}
 		}
 	}
 }
@@ -161,25 +460,134 @@ impl Iterator for Parser {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use lexer::Token;
+	use lexer::{tokenize, Lexer, Token};
+	use diagnostic::{Span, Spanned};
 	use instructions::Instruction;
-	use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType};
+	use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, Operand, Address};
+	use instructions::{FloatAssignmentType, ConversionType};
 
-	use registers::Register;
+	use registers::{Register, FloatRegister};
 	#[test]
 	fn iter(){
-		let tokens = vec![Token::Identifier("str".to_string()), Token::Value(6), Token::Identifier("ra".to_string())];
-		let mut parser = Parser::new(tokens);
+		let tokens = vec![Spanned::new(Token::Identifier("str".to_string()), Span::new(1, 1, 3)),
+			Spanned::new(Token::Value(6), Span::new(1, 5, 1)),
+			Spanned::new(Token::Identifier("ra".to_string()), Span::new(1, 7, 2))];
+		let parser = Parser::new(tokens);
 		let mut output = vec![];
 
-		loop {
-			match parser.next() {
-				Some(c) => {
-					output.push(c);
-				},
-				None => break
-			}
+		for c in parser {
+			output.push(c.unwrap());
 		}
-		assert_eq!(output, vec![Instruction::Assignment(AssignmentType::STR(6), Register::RA)]);
+		assert_eq!(output, vec![Spanned::new(Instruction::Assignment(AssignmentType::STR(6), Register::RA), Span::new(1, 1, 3))]);
 	}
-}
\ No newline at end of file
+	#[test]
+	fn spans(){
+		let program = parse_spanned(tokenize("main:
+	.alias counter rc
+	str 1 counter
+
+	.data 0 \"hi\"
+	hlt").unwrap()).unwrap();
+		let spans = program.iter().map(|i| i.span).collect::<Vec<_>>();
+		assert_eq!(spans, vec![Span::new(1, 1, 5), Span::new(3, 5, 3), Span::new(5, 5, 1), Span::new(6, 5, 3)]);
+	}
+	#[test]
+	fn invalid_register(){
+		let errors = parse(tokenize("main:\n\tstr 6 rz\n\thlt").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("unknown register `rz`", Span::new(2, 11, 2))]);
+	}
+	#[test]
+	fn missing_argument(){
+		let errors = parse(tokenize("add ra rb\nhlt").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("expected register, found end of line", Span::new(1, 10, 1))]);
+	}
+	#[test]
+	fn invalid_literal(){
+		let errors = parse(Lexer::new("str 99999999999999999999 ra\nld [ra + 99999999999] rb").collect()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("literal `99999999999999999999` does not fit in 64 bits", Span::new(1, 5, 20)),
+			Diagnostic::new("literal `99999999999` does not fit in 32 bits", Span::new(2, 10, 11))]);
+	}
+	#[test]
+	fn commas(){
+		let program = parse(tokenize("add ra, rb, rc\njz ra, end").unwrap()).unwrap();
+		assert_eq!(program, vec![Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+			Instruction::Branch(BranchType::ZERO(Register::RA), "end".to_string())]);
+	}
+	#[test]
+	fn immediate(){
+		let program = parse(tokenize("add ra 1 ra\nshl 'a' rb rc").unwrap()).unwrap();
+		assert_eq!(program, vec![Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+			Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LEFT), Operand::Imm(97), Operand::Reg(Register::RB), Register::RC)]);
+		let errors = parse(tokenize("add ra rb 1").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("expected register, found `1`", Span::new(1, 11, 1))]);
+	}
+	#[test]
+	fn registers(){
+		let program = parse(tokenize(".alias counter r7\n.alias total rb\nadd counter 1 counter\ncpy r31 total").unwrap()).unwrap();
+		assert_eq!(program, vec![Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::from_index(7).unwrap()), Operand::Imm(1), Register::from_index(7).unwrap()),
+			Instruction::Assignment(AssignmentType::CPY(Register::from_index(31).unwrap()), Register::RB)]);
+		let errors = parse(tokenize(".alias ra rb\n.alias x r255\nout counter").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("`ra` is already a register name", Span::new(1, 8, 2)),
+			Diagnostic::new("unknown register `r255`", Span::new(2, 10, 4)),
+			Diagnostic::new("unknown register `counter`", Span::new(3, 5, 7))]);
+	}
+	#[test]
+	fn aliases_carry_over(){
+		let mut aliases = Aliases::default();
+		assert_eq!(parse_with(tokenize(".alias counter rc\n.alias half fb").unwrap(), &mut aliases), Ok(vec![]));
+		assert!(parse_with(tokenize(".alias total rd\nout nowhere").unwrap(), &mut aliases).is_err());
+		let program = parse_with(tokenize("str 1 counter\nfcpy half fa").unwrap(), &mut aliases).unwrap();
+		assert_eq!(program, vec![Instruction::Assignment(AssignmentType::STR(1), Register::RC),
+			Instruction::FloatAssignment(FloatAssignmentType::CPY(FloatRegister::FB), FloatRegister::FA)]);
+		let errors = parse_with(tokenize("out total").unwrap(), &mut aliases).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("unknown register `total`", Span::new(1, 5, 5))]);
+	}
+	#[test]
+	fn floats(){
+		let program = parse(tokenize(".alias x f7\nfstr 1.5 fa\nfstr 2 x\nitof ra fb\nftoi fb ra").unwrap()).unwrap();
+		let f7 = FloatRegister::from_index(7).unwrap();
+		assert_eq!(program, vec![Instruction::FloatAssignment(FloatAssignmentType::STR(1.5), FloatRegister::FA),
+			Instruction::FloatAssignment(FloatAssignmentType::STR(2.0), f7),
+			Instruction::Conversion(ConversionType::TOFLOAT, Register::RA, FloatRegister::FB),
+			Instruction::Conversion(ConversionType::TOINT, Register::RA, FloatRegister::FB)]);
+		let errors = parse(tokenize("fadd fa ra fb\nstr 1.5 ra").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![Diagnostic::new("unknown register `ra`", Span::new(1, 9, 2)),
+			Diagnostic::new("expected value, found `1.5`", Span::new(2, 5, 3))]);
+	}
+	#[test]
+	fn memory(){
+		let program = parse(tokenize("ld [ra] rb\nst rb [rc + 4]\nld [rd -1], re").unwrap()).unwrap();
+		assert_eq!(program, vec![Instruction::Memory(MemoryType::LOAD, Register::RB, Address { base: Register::RA, offset: 0 }),
+			Instruction::Memory(MemoryType::STORE, Register::RB, Address { base: Register::RC, offset: 4 }),
+			Instruction::Memory(MemoryType::LOAD, Register::RE, Address { base: Register::RD, offset: -1 })]);
+	}
+	#[test]
+	fn invalid_address(){
+		let errors = parse(tokenize("ld ra rb\nst rb [rc 4]\nld [rd + 1 re").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![
+			Diagnostic::new("expected `[`, found `ra`", Span::new(1, 4, 2)),
+			Diagnostic::new("expected `+` or `]`, found `4`", Span::new(2, 11, 1)),
+			Diagnostic::new("expected `]`, found `re`", Span::new(3, 12, 2)),
+		]);
+	}
+	#[test]
+	fn data(){
+		let program = parse(tokenize(".data 100 \"result: \"\nprints ra").unwrap()).unwrap();
+		assert_eq!(program, vec![Instruction::Control(ControlType::DATA(100, "result: ".to_string())), Instruction::IO(IOType::PRINTS, Register::RA)]);
+		let errors = parse(tokenize(".text 100\n.data \"x\"\n.data 4 5").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![
+			Diagnostic::new("unknown directive `.text`", Span::new(1, 2, 4)),
+			Diagnostic::new("expected value, found `\"x\"`", Span::new(2, 7, 3)),
+			Diagnostic::new("expected string, found `5`", Span::new(3, 9, 1)),
+		]);
+	}
+	#[test]
+	fn collects_all_errors(){
+		let errors = parse(tokenize("main:\nfoo ra\nstr ra rb\nout ra\njmp 5\nhlt").unwrap()).unwrap_err();
+		assert_eq!(errors, vec![
+			Diagnostic::new("unknown instruction `foo`", Span::new(2, 1, 3)),
+			Diagnostic::new("expected value, found `ra`", Span::new(3, 5, 2)),
+			Diagnostic::new("expected label, found `5`", Span::new(5, 5, 1)),
+		]);
+	}
+}
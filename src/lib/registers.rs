@@ -1,26 +1,115 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Register {
-    RA,
-    RB,
-    RC,
-    RD,
-    RE,
-    RF,
-}
+/// Registers are numbered `r0`, `r1`, ..., with `ra`..`rf` as the legacy
+/// names of `r0`..`r5`. How many a program may use is set by the VM's
+/// `Config`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Register(u8);
+
+/// The most registers a VM can have. Index 0xff is left free so bytecode can
+/// use it to mark an immediate operand.
+pub const MAX_REGISTERS: usize = 255;
+
+/// A register in the separate floating point bank, numbered `f0`, `f1`, ...,
+/// with `fa`..`ff` as the names of `f0`..`f5`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct FloatRegister(u8);
 
-impl From<String> for Register {
-    fn from(string: String) -> Register {
-        match string.as_ref() {
+const NAMES: [&str; 6] = ["ra", "rb", "rc", "rd", "re", "rf"];
+const FLOAT_NAMES: [&str; 6] = ["fa", "fb", "fc", "fd", "fe", "ff"];
 
-            "ra" => Register::RA,
-            "rb" => Register::RB,
-            "rc" => Register::RC,
-            "rd" => Register::RD,
-            "re" => Register::RE,
-            "rf" => Register::RF,
-            _ => unreachable!(),
+impl Register {
+    pub const RA: Register = Register(0);
+    pub const RB: Register = Register(1);
+    pub const RC: Register = Register(2);
+    pub const RD: Register = Register(3);
+    pub const RE: Register = Register(4);
+    pub const RF: Register = Register(5);
 
+    pub fn from_name(name: &str) -> Option<Register> {
+        index_from_name(name, 'r', &NAMES).map(Register)
+    }
+    pub fn from_index(index: u8) -> Option<Register> {
+        if (index as usize) < MAX_REGISTERS {
+            Some(Register(index))
+        } else {
+            None
         }
     }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+    /// The legacy name of the register, if it has one.
+    pub fn name(self) -> Option<&'static str> {
+        NAMES.get(self.index()).cloned()
+    }
 }
 
+impl FloatRegister {
+    pub const FA: FloatRegister = FloatRegister(0);
+    pub const FB: FloatRegister = FloatRegister(1);
+    pub const FC: FloatRegister = FloatRegister(2);
+    pub const FD: FloatRegister = FloatRegister(3);
+    pub const FE: FloatRegister = FloatRegister(4);
+    pub const FF: FloatRegister = FloatRegister(5);
+
+    pub fn from_name(name: &str) -> Option<FloatRegister> {
+        index_from_name(name, 'f', &FLOAT_NAMES).map(FloatRegister)
+    }
+    pub fn from_index(index: u8) -> Option<FloatRegister> {
+        if (index as usize) < MAX_REGISTERS {
+            Some(FloatRegister(index))
+        } else {
+            None
+        }
+    }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+    /// The letter name of the register, if it has one.
+    pub fn name(self) -> Option<&'static str> {
+        FLOAT_NAMES.get(self.index()).cloned()
+    }
+}
+
+/// Parses a register name that is either one of `names` or `prefix`
+/// followed by an index.
+fn index_from_name(name: &str, prefix: char, names: &[&str]) -> Option<u8> {
+    if let Some(index) = names.iter().position(|&n| n == name) {
+        return Some(index as u8);
+    }
+    match name.strip_prefix(prefix) {
+        Some(digits) if is_number(digits) => digits.parse().ok().filter(|&index: &u8| (index as usize) < MAX_REGISTERS),
+        _ => None,
+    }
+}
+
+/// Checks for a plain decimal number without leading zeroes.
+fn is_number(digits: &str) -> bool {
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) && (digits == "0" || !digits.starts_with('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn from_name() {
+        assert_eq!(Register::from_name("rc"), Some(Register::RC));
+        assert_eq!(Register::from_name("r2"), Some(Register::RC));
+        assert_eq!(Register::from_name("r31"), Register::from_index(31));
+        assert_eq!(Register::from_name("r254"), Register::from_index(254));
+        assert_eq!(Register::from_name("r255"), None);
+        assert_eq!(Register::from_name("r01"), None);
+        assert_eq!(Register::from_name("r+1"), None);
+        assert_eq!(Register::from_name("rg"), None);
+        assert_eq!(Register::from_name("x1"), None);
+        assert_eq!(Register::from_name("fa"), None);
+    }
+    #[test]
+    fn float_from_name() {
+        assert_eq!(FloatRegister::from_name("ff"), Some(FloatRegister::FF));
+        assert_eq!(FloatRegister::from_name("f1"), Some(FloatRegister::FB));
+        assert_eq!(FloatRegister::from_name("f254"), FloatRegister::from_index(254));
+        assert_eq!(FloatRegister::from_name("f255"), None);
+        assert_eq!(FloatRegister::from_name("ra"), None);
+        assert_eq!(FloatRegister::from_name("fg"), None);
+    }
+}
@@ -1,212 +1,841 @@
 use std::fmt;
+use std::error;
 use std::collections::HashMap;
 use std::cmp;
-use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType};
+use std::time::{Duration, Instant};
+use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, StackType, Operand, Address};
+use instructions::{FloatArithmaticType, FloatUnaryType, FloatAssignmentType, FloatIOType, ConversionType};
 use instructions::Instruction;
-const REG_SIZE: usize = 6; 
+use io::{Input, InputError, TextInput, Output, TextOutput};
+use registers::{Register, FloatRegister, MAX_REGISTERS};
 
 
 pub type VMResult<T> = Result<T, VMError>;
 
-#[derive(Debug,PartialEq)]
-enum VMError {
+#[derive(Debug, PartialEq, Clone)]
+pub enum VMErrorKind {
     ZeroDivision,
+    /// An arithmetic result or shift amount out of range, under
+    /// `Overflow::Checked`.
+    Overflow,
     MissingExitInstruction,
     MissingMainLabel,
-    UndefinedLabel,
+    UndefinedLabel(String),
+    /// A label defined a second time.
+    DuplicateLabel(String),
+    /// An access to a memory address outside of the VM's memory.
+    SegFault(i64),
+    StackOverflow,
+    StackUnderflow,
+    /// The `in` instruction couldn't read a value.
+    Input(InputError),
+    /// An output instruction couldn't write its value.
+    Output(String),
+    /// A register beyond the number the VM was configured with.
+    InvalidRegister(Register),
+    InvalidFloatRegister(FloatRegister),
+    /// A value printed as a character isn't a valid Unicode scalar value.
+    InvalidCharacter(i64),
+    /// A literal too large for a 32-bit word.
+    LiteralOutOfRange(i64),
+    /// The program executed `Config::fuel` instructions without halting.
+    OutOfFuel,
+    /// The program ran for longer than `Config::timeout`.
+    Timeout,
+    /// Execution ran past the end of the program, or returned to an address
+    /// outside it.
+    IpOutOfRange(i64),
+    /// `Config` asks for more integer or float registers than
+    /// `MAX_REGISTERS`.
+    TooManyRegisters(usize),
+    /// `Config::memory_size` words couldn't be allocated.
+    MemoryTooLarge(usize),
 }
+
+/// An error raised while loading or executing a program, along with the state
+/// of the machine at the point it stopped.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VMError {
+    pub kind: VMErrorKind,
+    /// Index of the failing instruction in the program.
+    pub ip: usize,
+    /// The failing instruction, if the error was raised while executing one.
+    pub instruction: Option<Instruction>,
+    pub registers: Vec<i64>,
+}
+
+impl fmt::Display for VMErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VMErrorKind::ZeroDivision => write!(f, "division by zero"),
+            VMErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            VMErrorKind::MissingExitInstruction => write!(f, "program has no `hlt` instruction"),
+            VMErrorKind::MissingMainLabel => write!(f, "program has no `main` label"),
+            VMErrorKind::UndefinedLabel(ref label) => write!(f, "undefined label `{}`", label),
+            VMErrorKind::DuplicateLabel(ref label) => write!(f, "label `{}` is defined more than once", label),
+            VMErrorKind::SegFault(address) => write!(f, "segmentation fault accessing address {}", address),
+            VMErrorKind::StackOverflow => write!(f, "stack overflow"),
+            VMErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            VMErrorKind::Input(ref error) => write!(f, "{}", error),
+            VMErrorKind::Output(ref message) => write!(f, "failed to write output: {}", message),
+            VMErrorKind::InvalidRegister(register) => write!(f, "register `{}` is out of range", register),
+            VMErrorKind::InvalidFloatRegister(register) => write!(f, "register `{}` is out of range", register),
+            VMErrorKind::InvalidCharacter(value) => write!(f, "{} is not a valid character", value),
+            VMErrorKind::LiteralOutOfRange(value) => write!(f, "literal {} does not fit in 32 bits", value),
+            VMErrorKind::OutOfFuel => write!(f, "ran out of fuel"),
+            VMErrorKind::Timeout => write!(f, "timed out"),
+            VMErrorKind::IpOutOfRange(ip) => write!(f, "instruction pointer {} is outside the program", ip),
+            VMErrorKind::TooManyRegisters(count) => write!(f, "a VM can have at most {} registers, not {}", MAX_REGISTERS, count),
+            VMErrorKind::MemoryTooLarge(size) => write!(f, "can't allocate {} words of memory", size),
+        }
+    }
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.instruction {
+            Some(ref instruction) => {
+                write!(f, "{} at ip {} ({})\nRegisters: {:?}", self.kind, self.ip, instruction, self.registers)
+            },
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl error::Error for VMError {}
+
 impl fmt::Debug for VM {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Registers: \n {:?} \n Jump Map:\n {:?}", self.registers,  self.jump_map)
+        write!(f, "Registers: \n {:?} \n Float Registers: \n {:?} \n Stack:\n {:?} \n Jump Map:\n {:?}", self.registers, self.float_registers, self.stack, self.jump_map)
+    }
+}
+
+/// How arithmetic and shifts behave when the result doesn't fit in a
+/// register, including `i32::MIN / -1`, `shl` moving bits past the sign bit
+/// and shift amounts outside `0..32` (or their 64-bit equivalents).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Overflow {
+    /// Wrap around in two's complement. Shift amounts are masked to the word
+    /// size.
+    Wrapping,
+    /// Stop with `VMErrorKind::Overflow`.
+    Checked,
+    /// Clamp to the nearest representable value. Out of range shifts move
+    /// every bit out, leaving 0 (or -1 for `shr` of a negative value).
+    Saturating,
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        match name {
+            "wrapping" => Some(Overflow::Wrapping),
+            "checked" => Some(Overflow::Checked),
+            "saturating" => Some(Overflow::Saturating),
+            _ => None,
+        }
+    }
+    /// Picks between a wrapped result and its saturated equivalent.
+    fn apply(self, (wrapped, overflowed): (i64, bool), saturated: i64) -> Result<i64, VMErrorKind> {
+        if !overflowed {
+            return Ok(wrapped);
+        }
+        match self {
+            Overflow::Wrapping => Ok(wrapped),
+            Overflow::Checked => Err(VMErrorKind::Overflow),
+            Overflow::Saturating => Ok(saturated),
+        }
+    }
+}
+
+/// An instruction prepared for execution by `VM::load`, with its branch
+/// target resolved to an index in the program.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Lowered {
+    IO(IOType, Register),
+    Arithmatic(ArithmaticType, Operand, Operand, Register),
+    Bitwise(BitwiseType, Operand, Operand, Register),
+    Unary(UnaryType, Register, Register),
+    Compare(ComparisonType, Register, Register, Register),
+    Branch(BranchType, usize),
+    Assignment(AssignmentType, Register),
+    Memory(MemoryType, Register, Address),
+    Stack(StackType, Register),
+    FloatArithmatic(FloatArithmaticType, FloatRegister, FloatRegister, FloatRegister),
+    FloatUnary(FloatUnaryType, FloatRegister, FloatRegister),
+    FloatAssignment(FloatAssignmentType, FloatRegister),
+    FloatIO(FloatIOType, FloatRegister),
+    Conversion(ConversionType, Register, FloatRegister),
+    Nop,
+    Hlt,
+    Ret,
+    /// Labels and `.data`, which have done their work by the time the
+    /// program runs, so `step` passes over them.
+    Directive,
+}
+
+/// The state of the VM after `VM::step` or `VM::run_for`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StepResult {
+    /// Execution can continue from `ip`.
+    Running,
+    /// The program has executed `hlt`, or no program is running.
+    Halted,
+}
+
+/// The width of the integer registers, memory words and stack slots.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WordSize {
+    Bits32,
+    Bits64,
+}
+
+impl WordSize {
+    pub fn from_bits(bits: u32) -> Option<WordSize> {
+        match bits {
+            32 => Some(WordSize::Bits32),
+            64 => Some(WordSize::Bits64),
+            _ => None,
+        }
+    }
+    pub fn bits(self) -> u32 {
+        match self {
+            WordSize::Bits32 => 32,
+            WordSize::Bits64 => 64,
+        }
+    }
+    /// The smallest and largest signed values of a word.
+    pub fn range(self) -> (i64, i64) {
+        match self {
+            WordSize::Bits32 => (i32::MIN as i64, i32::MAX as i64),
+            WordSize::Bits64 => (i64::MIN, i64::MAX),
+        }
+    }
+    /// Keeps the low bits of `value`, sign extended from the word size.
+    pub fn truncate(self, value: i64) -> i64 {
+        match self {
+            WordSize::Bits32 => value as i32 as i64,
+            WordSize::Bits64 => value,
+        }
+    }
+    /// Reinterprets a word as unsigned.
+    fn unsigned(self, value: i64) -> u64 {
+        match self {
+            WordSize::Bits32 => value as u32 as u64,
+            WordSize::Bits64 => value as u64,
+        }
+    }
+    /// Whether a literal can be loaded into a word, either as a signed value
+    /// or as the bit pattern of an unsigned one.
+    pub fn fits(self, value: i64) -> bool {
+        match self {
+            WordSize::Bits32 => value >= i32::MIN as i64 && value <= u32::MAX as i64,
+            WordSize::Bits64 => true,
+        }
     }
 }
 
+/// Settings for a `VM`, passed to `VM::with_config`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Config {
+    /// Size of the VM's memory, in words.
+    pub memory_size: usize,
+    /// Maximum number of values on the stack, including return addresses.
+    pub stack_size: usize,
+    pub overflow: Overflow,
+    /// Number of general purpose registers, `r0` up to at most
+    /// `MAX_REGISTERS`. The default of 6 covers `ra`..`rf`.
+    pub registers: usize,
+    /// Number of float registers, likewise covering `fa`..`ff` by default.
+    pub float_registers: usize,
+    pub word_size: WordSize,
+    /// The most instructions a program may execute, if limited, counted
+    /// afresh by `VM::load` and `VM::extend`. Labels and `.data` directives
+    /// aren't instructions, so don't count.
+    pub fuel: Option<u64>,
+    /// How long a program may run for, measured from `VM::load` or
+    /// `VM::extend`. This is only checked between instructions, so it can't
+    /// interrupt a blocking `in`.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config { memory_size: 1024, stack_size: 256, overflow: Overflow::Wrapping, registers: 6, float_registers: 6, word_size: WordSize::Bits32, fuel: None, timeout: None }
+    }
+}
+
+/// How many instructions run between checks of the clock against
+/// `Config::timeout`.
+const TIMEOUT_INTERVAL: u64 = 1024;
+
 pub struct VM {
     program: Vec<Instruction>,
-    registers: [i32; REG_SIZE],
+    /// The lowered form of `program` that `step` executes.
+    code: Vec<Lowered>,
+    registers: Vec<i64>,
+    float_registers: Vec<f64>,
+    memory: Vec<i64>,
+    stack: Vec<i64>,
+    input: Box<dyn Input + Send>,
+    output: Box<dyn Output + Send>,
+    config: Config,
     ip: usize,
     jump_map: HashMap<String, usize>,
     running: bool,
+    /// Instructions executed since the program was loaded.
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Default for VM {
+    fn default() -> VM {
+        VM::new()
+    }
 }
 
 impl VM {
     pub fn new() -> VM {
-        VM {  program: Vec::new(), registers: [0; REG_SIZE], ip: 0, jump_map: HashMap::new(), running: true}
-    }
-    pub fn run(&mut self, program: Vec<Instruction>) {
-        
-        self.jump_map = self.build_jump_map(program.clone()); 
-        match self.jump_map.get(&"main".to_string()) {
-            Some(&ip) => self.ip = ip,
-            _ => panic!("VMError: {:?}", VMError::MissingMainLabel),
-        }
-        match program.iter().position(|hlt| *hlt == Instruction::HLT) {
-            Some(_) => {},
-            _ => panic!("VMError: {:?}", VMError::MissingExitInstruction),
-        }
-        
-        while self.running {
-            let instruction = &program[self.ip];
-
-            match self.eval(instruction) {
-                Ok(_) => {},
-                Err(e) => {
-                    println!("{:?}", self);
-                    panic!("VMError: {:?} on ip {:?}", e, self.ip + 1);	
-                }
+        VM::with_config(Config::default())
+    }
+    /// Creates a VM without a program. The registers and memory are allocated
+    /// by the first `load` or `extend`, which reports a `Config` that can't be
+    /// used.
+    pub fn with_config(config: Config) -> VM {
+        VM {  program: Vec::new(), code: Vec::new(), registers: Vec::new(), float_registers: Vec::new(), memory: Vec::new(), stack: Vec::new(), input: Box::new(TextInput::stdin()), output: Box::new(TextOutput::stdout()), config, ip: 0, jump_map: HashMap::new(), running: false, steps: 0, deadline: None}
+    }
+    /// Replaces the source of values for the `in` instruction, which is stdin
+    /// by default.
+    pub fn set_input<I: Input + Send + 'static>(&mut self, input: I) {
+        self.input = Box::new(input);
+    }
+    /// Replaces the sink for output instructions, which is stdout by default.
+    pub fn set_output<O: Output + Send + 'static>(&mut self, output: O) {
+        self.output = Box::new(output);
+    }
+    pub fn run(&mut self, program: Vec<Instruction>) -> VMResult<()> {
+        self.load(program)?;
+        while self.step()? == StepResult::Running {}
+        Ok(())
+    }
+    /// Checks a program, clears the stack, zeroes the registers and memory,
+    /// writes the program's `.data` into memory and moves to `main`, ready to
+    /// be executed with `step`. If the program can't be loaded, any previous
+    /// program is stopped but otherwise left as it was.
+    pub fn load(&mut self, program: Vec<Instruction>) -> VMResult<()> {
+        self.running = false;
+        self.allocate()?;
+        let jump_map = self.build_jump_map(&program, 0, &HashMap::new())?;
+        let main = match jump_map.get("main") {
+            Some(&ip) => ip,
+            _ => return Err(self.error(VMErrorKind::MissingMainLabel, None)),
+        };
+        if !program.contains(&Instruction::HLT) {
+            return Err(self.error(VMErrorKind::MissingExitInstruction, None));
+        }
+        self.code = self.prepare(&program, 0, &jump_map, &HashMap::new())?;
+        self.reset();
+        self.load_data(&program);
+        self.jump_map = jump_map;
+        self.program = program;
+        self.ip = main;
+        self.running = true;
+        self.restart_limits();
+        Ok(())
+    }
+    /// Executes the instruction at `ip`. An error stops the program, leaving
+    /// `ip` at the failing instruction, or for `OutOfFuel` and `Timeout` the
+    /// instruction that would have run next.
+    pub fn step(&mut self) -> VMResult<StepResult> {
+        if !self.running {
+            return Ok(StepResult::Halted);
+        }
+        if self.ip >= self.code.len() {
+            self.running = false;
+            return Err(self.error(VMErrorKind::IpOutOfRange(self.ip as i64), None));
+        }
+        let result = match self.code[self.ip] {
+            Lowered::Directive => Ok(()),
+            instruction => self.check_limits().and_then(|_| {
+                self.steps += 1;
+                self.eval(instruction)
+            }),
+        };
+        if let Err(kind) = result {
+            self.running = false;
+            return Err(self.error(kind, Some(self.program[self.ip].clone())));
+        }
+        self.ip += 1;
+        if self.running {
+            Ok(StepResult::Running)
+        } else {
+            Ok(StepResult::Halted)
+        }
+    }
+    /// Executes at most `steps` instructions, returning early if the program
+    /// halts.
+    pub fn run_for(&mut self, steps: usize) -> VMResult<StepResult> {
+        for _ in 0..steps {
+            if self.step()? == StepResult::Halted {
+                return Ok(StepResult::Halted);
+            }
+        }
+        if self.running {
+            Ok(StepResult::Running)
+        } else {
+            Ok(StepResult::Halted)
+        }
+    }
+    /// The number of instructions executed since the program was loaded or
+    /// extended, not counting labels and `.data` directives passed through.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    /// Whether a loaded program has yet to halt or fail.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    /// The index of the next instruction to execute.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+    /// The loaded program.
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+    /// Where `label` is in the loaded program.
+    pub fn label(&self, label: &str) -> Option<usize> {
+        self.jump_map.get(label).cloned()
+    }
+    /// The integer registers, empty until a program is loaded.
+    pub fn registers(&self) -> &[i64] {
+        &self.registers
+    }
+    pub fn float_registers(&self) -> &[f64] {
+        &self.float_registers
+    }
+    /// The VM's memory, one word per address, empty until a program is
+    /// loaded.
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+    /// The stack, with the most recently pushed value last.
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+    /// Overwrites a register, truncating `value` to the word size.
+    pub fn set_register(&mut self, register: Register, value: i64) -> VMResult<()> {
+        if register.index() >= self.registers.len() {
+            return Err(self.error(VMErrorKind::InvalidRegister(register), None));
+        }
+        self.registers[register.index()] = self.config.word_size.truncate(value);
+        Ok(())
+    }
+    /// Appends instructions to the program and moves to the first of them,
+    /// ready to be executed with `step`, keeping the registers, memory and
+    /// stack. Branches may target labels anywhere in the combined program.
+    /// Unlike `load`, neither `main` nor `hlt` is required. If the new
+    /// instructions can't be loaded the program is left as it was. Fuel and
+    /// the timeout start again, as they do for `load`.
+    pub fn extend(&mut self, instructions: Vec<Instruction>) -> VMResult<()> {
+        self.allocate()?;
+        let start = self.program.len();
+        let labels = self.build_jump_map(&instructions, start, &self.jump_map)?;
+        let code = self.prepare(&instructions, start, &labels, &self.jump_map)?;
+        self.load_data(&instructions);
+        self.code.extend(code);
+        self.jump_map.extend(labels);
+        self.program.extend(instructions);
+        self.ip = start;
+        self.running = true;
+        self.restart_limits();
+        Ok(())
+    }
+    fn error(&self, kind: VMErrorKind, instruction: Option<Instruction>) -> VMError {
+        VMError {
+            kind,
+            ip: self.ip,
+            instruction,
+            registers: self.registers.clone(),
+        }
+    }
+    /// Ensures every register the program uses exists, so evaluation can
+    /// index the register file directly, that every literal fits in a word
+    /// and that every `.data` directive fits in memory. `start` is the
+    /// position of the first instruction in the whole program.
+    fn check_instructions(&self, instructions: &[Instruction], start: usize) -> VMResult<()> {
+        for (position, instruction) in instructions.iter().enumerate() {
+            if let Err(kind) = self.check_instruction(instruction) {
+                return Err(VMError { ip: start + position, ..self.error(kind, Some(instruction.clone())) });
             }
-            self.ip += 1;
-        }
-    }
-    pub fn interactive(&mut self, instruction: Instruction) {
-        self.program.push(instruction);
-    
-        let ref i = self.program[self.ip].clone();
-        match i {
-                &Instruction::Control(ref kind) => {
-                    match kind {
-                        &ControlType::LBL(ref s) => {
-                            self.jump_map.insert(s.clone(),self.ip)
-                        },
-                    }
+        }
+        Ok(())
+    }
+    fn check_config(&self) -> VMResult<()> {
+        match cmp::max(self.config.registers, self.config.float_registers) {
+            count if count > MAX_REGISTERS => Err(self.error(VMErrorKind::TooManyRegisters(count), None)),
+            _ => Ok(()),
+        }
+    }
+    /// Sizes the registers and memory from the config the first time a
+    /// program is loaded.
+    fn allocate(&mut self) -> VMResult<()> {
+        self.check_config()?;
+        let size = self.config.memory_size;
+        if self.memory.len() != size {
+            let mut memory = Vec::new();
+            if memory.try_reserve_exact(size).is_err() {
+                return Err(self.error(VMErrorKind::MemoryTooLarge(size), None));
+            }
+            memory.resize(size, 0);
+            self.memory = memory;
+        }
+        self.registers.resize(self.config.registers, 0);
+        self.float_registers.resize(self.config.float_registers, 0.0);
+        Ok(())
+    }
+    /// Starts counting fuel and time afresh. A timeout too long to represent
+    /// as an `Instant` never expires.
+    fn restart_limits(&mut self) {
+        self.steps = 0;
+        self.deadline = self.config.timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    }
+    /// Clears what a previous program left behind.
+    fn reset(&mut self) {
+        self.stack.clear();
+        self.registers.fill(0);
+        self.float_registers.fill(0.0);
+        self.memory.fill(0);
+    }
+    fn check_limits(&self) -> Result<(), VMErrorKind> {
+        if self.config.fuel.is_some_and(|fuel| self.steps >= fuel) {
+            return Err(VMErrorKind::OutOfFuel);
+        }
+        match self.deadline {
+            Some(deadline) if self.steps.is_multiple_of(TIMEOUT_INTERVAL) && Instant::now() >= deadline => Err(VMErrorKind::Timeout),
+            _ => Ok(()),
+        }
+    }
+    fn check_instruction(&self, instruction: &Instruction) -> Result<(), VMErrorKind> {
+        if let Some(value) = instruction.literals().into_iter().find(|&value| !self.config.word_size.fits(value)) {
+            return Err(VMErrorKind::LiteralOutOfRange(value));
+        }
+        if let Some(register) = instruction.registers().into_iter().find(|register| register.index() >= self.registers.len()) {
+            return Err(VMErrorKind::InvalidRegister(register));
+        }
+        if let Some(register) = instruction.float_registers().into_iter().find(|register| register.index() >= self.float_registers.len()) {
+            return Err(VMErrorKind::InvalidFloatRegister(register));
+        }
+        // `.data` is only written once every directive is known to fit, so a
+        // failed load leaves memory alone.
+        if let Instruction::Control(ControlType::DATA(address, ref text)) = *instruction {
+            let end = address as i64 + text.chars().count() as i64;
+            if let Some(location) = (address as i64..=end).find(|&location| self.location(location).is_err()) {
+                return Err(VMErrorKind::SegFault(location));
+            }
+        }
+        Ok(())
+    }
+    /// Checks and lowers instructions that will be placed at `start`, without
+    /// changing the VM, so a failure leaves a loaded program as it was.
+    /// `labels` are those the instructions define and `earlier` those already
+    /// in the program.
+    fn prepare(&self, instructions: &[Instruction], start: usize, labels: &HashMap<String, usize>, earlier: &HashMap<String, usize>) -> VMResult<Vec<Lowered>> {
+        self.check_instructions(instructions, start)?;
+        self.lower(instructions, start, labels, earlier)
+    }
+    /// Resolves every branch target up front, so a taken branch doesn't look
+    /// its label up and undefined labels are found before the program starts.
+    fn lower(&self, instructions: &[Instruction], start: usize, labels: &HashMap<String, usize>, earlier: &HashMap<String, usize>) -> VMResult<Vec<Lowered>> {
+        let mut code = Vec::with_capacity(instructions.len());
+        for (position, instruction) in instructions.iter().enumerate() {
+            code.push(match *instruction {
+                Instruction::IO(kind, register) => Lowered::IO(kind, register),
+                Instruction::Arithmatic(kind, source, target, destination) => Lowered::Arithmatic(kind, source, target, destination),
+                Instruction::Bitwise(kind, source, target, destination) => Lowered::Bitwise(kind, source, target, destination),
+                Instruction::Unary(kind, source, destination) => Lowered::Unary(kind, source, destination),
+                Instruction::Compare(kind, source, target, destination) => Lowered::Compare(kind, source, target, destination),
+                Instruction::Branch(kind, ref label) => match labels.get(label).or_else(|| earlier.get(label)) {
+                    Some(&target) => Lowered::Branch(kind, target),
+                    None => {
+                        let error = self.error(VMErrorKind::UndefinedLabel(label.clone()), Some(instruction.clone()));
+                        return Err(VMError { ip: start + position, ..error });
+                    },
                 },
-                _ => Some(0)
-        };
-        match self.eval(&i) {
-            Ok(_) => {},
-            Err(e) => {
-                println!("{:?}", self);
-                println!("VMError: {:?} on ip {:?}", e, self.ip + 1); 
+                Instruction::Control(_) => Lowered::Directive,
+                Instruction::Assignment(kind, register) => Lowered::Assignment(kind, register),
+                Instruction::Memory(kind, register, address) => Lowered::Memory(kind, register, address),
+                Instruction::Stack(kind, register) => Lowered::Stack(kind, register),
+                Instruction::FloatArithmatic(kind, source, target, destination) => Lowered::FloatArithmatic(kind, source, target, destination),
+                Instruction::FloatUnary(kind, source, destination) => Lowered::FloatUnary(kind, source, destination),
+                Instruction::FloatAssignment(kind, register) => Lowered::FloatAssignment(kind, register),
+                Instruction::FloatIO(kind, register) => Lowered::FloatIO(kind, register),
+                Instruction::Conversion(kind, register, float) => Lowered::Conversion(kind, register, float),
+                Instruction::NOP => Lowered::Nop,
+                Instruction::HLT => Lowered::Hlt,
+                Instruction::RET => Lowered::Ret,
+            });
+        }
+        Ok(code)
+    }
+    /// Writes every `.data` directive into memory before the program starts,
+    /// once `prepare` has checked that they fit.
+    fn load_data(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            if let Instruction::Control(ControlType::DATA(address, ref text)) = *instruction {
+                self.store_string(address as usize, text);
             }
         }
-        self.ip += 1;
-        
     }
-    fn build_jump_map(&mut self, program: Vec<Instruction>) -> HashMap<String, usize> {
+    /// Stores `text` one character per word, followed by a zero terminator.
+    fn store_string(&mut self, address: usize, text: &str) {
+        for (i, c) in text.chars().chain(Some('\0')).enumerate() {
+            self.memory[address + i] = c as i64;
+        }
+    }
+    /// Reads a zero terminated string starting at `address`.
+    fn load_string(&self, address: i64) -> Result<String, VMErrorKind> {
+        let mut text = String::new();
+        for i in 0.. {
+            match self.memory[self.location(address.saturating_add(i))?] {
+                0 => break,
+                value => text.push(character(value)?),
+            }
+        }
+        Ok(text)
+    }
+    fn operand(&self, operand: Operand) -> i64 {
+        match operand {
+            Operand::Reg(register) => self.registers[register.index()],
+            Operand::Imm(value) => self.config.word_size.truncate(value),
+        }
+    }
+    fn address(&self, address: Address) -> Result<usize, VMErrorKind> {
+        self.location(self.registers[address.base.index()].saturating_add(address.offset as i64))
+    }
+    /// Fits an exact result into a word, according to the overflow mode.
+    fn narrow(&self, value: i128) -> Result<i64, VMErrorKind> {
+        let (min, max) = self.config.word_size.range();
+        let saturated = cmp::max(min as i128, cmp::min(max as i128, value)) as i64;
+        let wrapped = self.config.word_size.truncate(value as i64);
+        self.config.overflow.apply((wrapped, value != saturated as i128), saturated)
+    }
+    fn location(&self, location: i64) -> Result<usize, VMErrorKind> {
+        if location < 0 || location >= self.memory.len() as i64 {
+            return Err(VMErrorKind::SegFault(location));
+        }
+        Ok(location as usize)
+    }
+    fn write(&mut self, text: &str) -> Result<(), VMErrorKind> {
+        self.output.write(text).map_err(|e| VMErrorKind::Output(e.to_string()))
+    }
+    fn push(&mut self, value: i64) -> Result<(), VMErrorKind> {
+        if self.stack.len() >= self.config.stack_size {
+            return Err(VMErrorKind::StackOverflow);
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+    fn pop(&mut self) -> Result<i64, VMErrorKind> {
+        self.stack.pop().ok_or(VMErrorKind::StackUnderflow)
+    }
+    /// Finds the labels in instructions that will be placed at `start`,
+    /// rejecting any already defined by them or in `earlier`.
+    fn build_jump_map(&self, instructions: &[Instruction], start: usize, earlier: &HashMap<String, usize>) -> VMResult<HashMap<String, usize>> {
         let mut jump_map: HashMap<String, usize> = HashMap::new();
 
-        for (position, instruction) in program.iter().enumerate() {
-            match instruction {
-                &Instruction::Control(ref kind) => {
-                        match kind {
-                            &ControlType::LBL(ref s) => {
-                                jump_map.insert(s.clone(),position)
-                            },
-                        }
-                },
-                _ => Some(0),
-            };
-
+        for (position, instruction) in instructions.iter().enumerate() {
+            if let Instruction::Control(ControlType::LBL(ref s)) = *instruction {
+                if earlier.contains_key(s) || jump_map.insert(s.clone(), start + position).is_some() {
+                    let error = self.error(VMErrorKind::DuplicateLabel(s.clone()), Some(instruction.clone()));
+                    return Err(VMError { ip: start + position, ..error });
+                }
+            }
         }
-        jump_map
+        Ok(jump_map)
     }
-    fn eval(&mut self, instruction: &Instruction) -> VMResult<()> {
+    /// Jumps to `target` if the branch is taken. Execution continues from the
+    /// instruction after `target`, as `step` then advances `ip`.
+    fn branch(&mut self, kind: BranchType, target: usize) -> Result<(), VMErrorKind> {
+        let taken = match kind {
+            BranchType::UNCONDITIONAL => true,
+            BranchType::ZERO(register) => self.registers[register.index()] == 0,
+            BranchType::NOTZERO(register) => self.registers[register.index()] != 0,
+            BranchType::COMPARE(ref comparison, a, b) => compare(comparison, self.registers[a.index()], self.registers[b.index()], self.config.word_size),
+            BranchType::CALL => {
+                self.push(self.ip as i64)?;
+                true
+            },
+        };
+        if taken {
+            self.ip = target;
+        }
+        Ok(())
+    }
+    fn eval(&mut self, instruction: Lowered) -> Result<(), VMErrorKind> {
         match instruction {
-            &Instruction::NOP => {
+            Lowered::Nop | Lowered::Directive => {
                 Ok(())
             },
-            &Instruction::HLT => {
+            Lowered::Hlt => {
                 self.running = false;
                 Ok(())
             }
-            &Instruction::IO(ref kind, register) => {
-                match kind {
-                    &IOType::OUT => println!("{:?}", self.registers[register as usize]),
-                    &IOType::IN => unimplemented!(),
+            Lowered::Ret => {
+                let ip = self.pop()?;
+                if ip < 0 {
+                    return Err(VMErrorKind::IpOutOfRange(ip));
                 }
+                self.ip = ip as usize;
                 Ok(())
-            },
-            &Instruction::Arithmatic(ref kind, source, target, destination) => {
-                match kind {
-                    &ArithmaticType::ADD => {
-                        self.registers[destination as usize] = self.registers[source as usize] + self.registers[target as usize]
-                    },
-                    &ArithmaticType::SUB => {
-                        self.registers[destination as usize] = self.registers[source as usize] - self.registers[target as usize]
-                    },
-                    &ArithmaticType::MUL => {
-                        self.registers[destination as usize] = self.registers[source as usize] * self.registers[target as usize]
-                    },
-                    &ArithmaticType::DIV => {
-                        match self.registers[target as usize] {
-                            0 => return Err(VMError::ZeroDivision),
-                            _ => {
-                                self.registers[destination as usize] = self.registers[source as usize] / self.registers[target as usize];
-                            }
+            }
+            Lowered::IO(ref kind, register) => {
+                match *kind {
+                    IOType::OUT => self.write(&format!("{}\n", self.registers[register.index()]))?,
+                    IOType::OUTN => self.write(&self.registers[register.index()].to_string())?,
+                    IOType::OUTC => self.write(&character(self.registers[register.index()])?.to_string())?,
+                    IOType::PRINTS => self.write(&self.load_string(self.registers[register.index()])?)?,
+                    IOType::IN => {
+                        let value = self.input.read().map_err(VMErrorKind::Input)?;
+                        let (min, max) = self.config.word_size.range();
+                        if value < min || value > max {
+                            return Err(VMErrorKind::Input(InputError::OutOfRange(value)));
                         }
-                    },
-                    &ArithmaticType::MAX => {
-                        self.registers[destination as usize] = cmp::max(self.registers[source as usize], self.registers[target as usize])
-                    },
-                    &ArithmaticType::MIN => {
-                        self.registers[destination as usize] = cmp::min(self.registers[source as usize],self.registers[target as usize])
+                        self.registers[register.index()] = value;
                     },
                 }
                 Ok(())
             },
-            &Instruction::Bitwise(ref kind, source, target, destination) => {
-                match kind {
-                    &BitwiseType::AND => {
-                        self.registers[destination as usize] = self.registers[source as usize] & self.registers[target as usize]
-                    },
-                    &BitwiseType::OR => {
-                        self.registers[destination as usize] = self.registers[source as usize] | self.registers[target as usize]
-                    },
-                    &BitwiseType::XOR => {
-                        self.registers[destination as usize] = self.registers[source as usize] ^ self.registers[target as usize]
+            Lowered::Arithmatic(ref kind, source, target, destination) => {
+                let (a, b) = (self.operand(source), self.operand(target));
+                let (wide_a, wide_b) = (a as i128, b as i128);
+                self.registers[destination.index()] = match *kind {
+                    ArithmaticType::ADD => self.narrow(wide_a + wide_b)?,
+                    ArithmaticType::SUB => self.narrow(wide_a - wide_b)?,
+                    ArithmaticType::MUL => self.narrow(wide_a * wide_b)?,
+                    ArithmaticType::DIV => {
+                        if b == 0 {
+                            return Err(VMErrorKind::ZeroDivision);
+                        }
+                        self.narrow(wide_a / wide_b)?
                     },
-                    &BitwiseType::SHIFT(ref kind) => {
-                        match kind {
-                            &ShiftType::LEFT => {
-                                 self.registers[destination as usize] = self.registers[source as usize] << self.registers[target as usize]
-                            },
-                            &ShiftType::RIGHT => {
-                                 self.registers[destination as usize] = self.registers[source as usize] >> self.registers[target as usize]
-                            },
+                    ArithmaticType::MOD => {
+                        if b == 0 {
+                            return Err(VMErrorKind::ZeroDivision);
                         }
-                    }
-                }
+                        self.narrow(wide_a % wide_b)?
+                    },
+                    ArithmaticType::MAX => cmp::max(a, b),
+                    ArithmaticType::MIN => cmp::min(a, b),
+                };
                 Ok(())
             },
-            &Instruction::Branch(ref kind, ref label) => {
-                match kind {
-                    &BranchType::UNCONDITIONAL => {
-                        match self.jump_map.get(label) {
-                            Some(&ip) => self.ip = ip,
-                            _ => return Err(VMError::UndefinedLabel),
-                        }
-                    },
-                    &BranchType::NOTZERO(register) => {
-                        if self.registers[register as usize] != 0 {
-                            match self.jump_map.get(label) {
-                                Some(&ip) => self.ip = ip,
-                                _ => return Err(VMError::UndefinedLabel),
-                            }
-                        }
+            Lowered::Bitwise(ref kind, source, target, destination) => {
+                let (a, b) = (self.operand(source), self.operand(target));
+                let (overflow, word_size) = (self.config.overflow, self.config.word_size);
+                let bits = word_size.bits();
+                let out_of_range = b < 0 || b >= bits as i64;
+                let amount = b as u32 & (bits - 1);
+                self.registers[destination.index()] = match *kind {
+                    BitwiseType::AND => a & b,
+                    BitwiseType::OR => a | b,
+                    BitwiseType::XOR => a ^ b,
+                    BitwiseType::SHIFT(ShiftType::LEFT) if out_of_range => overflow.apply((word_size.truncate(a << amount), true), 0)?,
+                    // Overflows like multiplying by a power of two.
+                    BitwiseType::SHIFT(ShiftType::LEFT) => self.narrow((a as i128) << amount)?,
+                    BitwiseType::SHIFT(ShiftType::RIGHT) => overflow.apply((a >> amount, out_of_range), a >> 63)?,
+                    BitwiseType::SHIFT(ShiftType::LOGICAL) => {
+                        let shifted = word_size.unsigned(a) >> amount;
+                        overflow.apply((word_size.truncate(shifted as i64), out_of_range), 0)?
                     },
-                    &BranchType::ZERO(register) => {
-                        if self.registers[register as usize] == 0 {
-                            match self.jump_map.get(label) {
-                                Some(&ip) => self.ip = ip,
-                                _ => return Err(VMError::UndefinedLabel),
-                            }
-                        }
-
-                    }
-                }
+                };
+                Ok(())
+            },
+            Lowered::Unary(ref kind, source, destination) => {
+                let a = self.registers[source.index()];
+                self.registers[destination.index()] = match *kind {
+                    UnaryType::NEG => self.narrow(-(a as i128))?,
+                    UnaryType::NOT => !a,
+                    UnaryType::ABS => self.narrow((a as i128).abs())?,
+                };
+                Ok(())
+            },
+            Lowered::Compare(ref kind, source, target, destination) => {
+                let (a, b) = (self.registers[source.index()], self.registers[target.index()]);
+                self.registers[destination.index()] = compare(kind, a, b, self.config.word_size) as i64;
                 Ok(())
             },
-            &Instruction::Control(ref kind) => {
-                match kind {
-                    &ControlType::LBL(ref label) => {
-                    }
+            Lowered::Branch(kind, target) => self.branch(kind, target),
+            Lowered::Assignment(ref kind, register) => {
+                match *kind {
+                    AssignmentType::STR(value) => self.registers[register.index()] = self.config.word_size.truncate(value),
+                    AssignmentType::CPY(register2) => self.registers[register.index()] = self.registers[register2.index()],
                 }
                 Ok(())
             }
-            &Instruction::Assignment(ref kind, register) => {
-                match kind {
-                    &AssignmentType::STR(value) => self.registers[register  as usize] = value,
-                    &AssignmentType::CPY(register2) => self.registers[register as usize] = self.registers[register2 as usize],
+            Lowered::Memory(ref kind, register, address) => {
+                let location = self.address(address)?;
+                match *kind {
+                    MemoryType::LOAD => self.registers[register.index()] = self.memory[location],
+                    MemoryType::STORE => self.memory[location] = self.registers[register.index()],
+                }
+                Ok(())
+            }
+            Lowered::Stack(ref kind, register) => {
+                match *kind {
+                    StackType::PUSH => self.push(self.registers[register.index()])?,
+                    StackType::POP => self.registers[register.index()] = self.pop()?,
+                }
+                Ok(())
+            }
+            Lowered::FloatArithmatic(ref kind, source, target, destination) => {
+                let (a, b) = (self.float_registers[source.index()], self.float_registers[target.index()]);
+                self.float_registers[destination.index()] = match *kind {
+                    FloatArithmaticType::ADD => a + b,
+                    FloatArithmaticType::SUB => a - b,
+                    FloatArithmaticType::MUL => a * b,
+                    FloatArithmaticType::DIV => a / b,
+                };
+                Ok(())
+            }
+            Lowered::FloatUnary(ref kind, source, destination) => {
+                let a = self.float_registers[source.index()];
+                self.float_registers[destination.index()] = match *kind {
+                    FloatUnaryType::SQRT => a.sqrt(),
+                    FloatUnaryType::NEG => -a,
+                    FloatUnaryType::ABS => a.abs(),
+                };
+                Ok(())
+            }
+            Lowered::FloatAssignment(ref kind, register) => {
+                self.float_registers[register.index()] = match *kind {
+                    FloatAssignmentType::STR(value) => value,
+                    FloatAssignmentType::CPY(source) => self.float_registers[source.index()],
+                };
+                Ok(())
+            }
+            Lowered::FloatIO(ref kind, register) => {
+                match *kind {
+                    FloatIOType::OUT => self.write(&format!("{:?}\n", self.float_registers[register.index()]))?,
+                    FloatIOType::OUTN => self.write(&format!("{:?}", self.float_registers[register.index()]))?,
+                }
+                Ok(())
+            }
+            Lowered::Conversion(ref kind, register, float) => {
+                match *kind {
+                    ConversionType::TOFLOAT => self.float_registers[float.index()] = self.registers[register.index()] as f64,
+                    ConversionType::TOINT => {
+                        let value = self.float_registers[float.index()];
+                        let (min, max) = self.config.word_size.range();
+                        // The upper bound is exclusive, as `max` rounds up to a power of two.
+                        let in_range = value.trunc() >= min as f64 && value.trunc() < -(min as f64);
+                        // `as` saturates out of range values and turns NaN into 0.
+                        let saturated = cmp::max(min, cmp::min(max, value as i64));
+                        // Exact below 2^127. Anything larger is a multiple of 2^64,
+                        // so wraps to 0, as do infinities and NaN.
+                        let wide = if value.trunc().abs() < 2f64.powi(127) { value.trunc() as i128 } else { 0 };
+                        let wrapped = self.config.word_size.truncate(wide as i64);
+                        self.registers[register.index()] = self.config.overflow.apply((wrapped, !in_range), saturated)?;
+                    },
                 }
                 Ok(())
             }
@@ -214,22 +843,47 @@ impl VM {
     }
 }
 
+fn compare(kind: &ComparisonType, a: i64, b: i64, word_size: WordSize) -> bool {
+    let (unsigned_a, unsigned_b) = (word_size.unsigned(a), word_size.unsigned(b));
+    match *kind {
+        ComparisonType::EQ => a == b,
+        ComparisonType::NE => a != b,
+        ComparisonType::LT => a < b,
+        ComparisonType::LE => a <= b,
+        ComparisonType::GT => a > b,
+        ComparisonType::GE => a >= b,
+        ComparisonType::LTU => unsigned_a < unsigned_b,
+        ComparisonType::LEU => unsigned_a <= unsigned_b,
+        ComparisonType::GTU => unsigned_a > unsigned_b,
+        ComparisonType::GEU => unsigned_a >= unsigned_b,
+    }
+}
+
+fn character(value: i64) -> Result<char, VMErrorKind> {
+    if value < 0 || value > u32::MAX as i64 {
+        return Err(VMErrorKind::InvalidCharacter(value));
+    }
+    ::std::char::from_u32(value as u32).ok_or(VMErrorKind::InvalidCharacter(value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use instructions::Instruction;
-    use registers::Register;
-    use instructions::{IOType, ArithmaticType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType};
+    use registers::{Register, FloatRegister};
+    use io::{ScriptedInput, InputError, BufferOutput};
+    use std::thread;
+    use instructions::{IOType, ArithmaticType, UnaryType, ComparisonType, BitwiseType, ShiftType, BranchType, ControlType, AssignmentType, MemoryType, StackType, Operand, Address};
     #[test]
     fn add() {
         let mut vm = VM::new();
         let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
-                    Instruction::Arithmatic(ArithmaticType::ADD, Register::RA, Register::RB, Register::RC),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RC as usize], 15);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RC.index()], 15);
     }
     #[test]
     fn sub() {
@@ -237,10 +891,10 @@ mod tests {
         let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
-                    Instruction::Arithmatic(ArithmaticType::SUB, Register::RA, Register::RB, Register::RC),
+                    Instruction::Arithmatic(ArithmaticType::SUB, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RC as usize], 5);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RC.index()], 5);
     }
     #[test]
     fn mul() {
@@ -248,10 +902,10 @@ mod tests {
         let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
-                    Instruction::Arithmatic(ArithmaticType::MUL, Register::RA, Register::RB, Register::RC),
+                    Instruction::Arithmatic(ArithmaticType::MUL, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RC as usize], 50);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RC.index()], 50);
     }
     #[test]
     fn div() {
@@ -259,22 +913,270 @@ mod tests {
          let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
-                    Instruction::Arithmatic(ArithmaticType::DIV, Register::RA, Register::RB, Register::RC),
+                    Instruction::Arithmatic(ArithmaticType::DIV, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RC.index()], 2);
+    }
+    #[test]
+    fn modulo() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-7), Register::RA),
+                    Instruction::Assignment(AssignmentType::STR(3), Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::MOD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RC.index()], -1);
+    }
+    #[test]
+    fn unary() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-5), Register::RA),
+                    Instruction::Unary(UnaryType::NEG, Register::RA, Register::RB),
+                    Instruction::Unary(UnaryType::NOT, Register::RA, Register::RC),
+                    Instruction::Unary(UnaryType::ABS, Register::RA, Register::RD),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(&vm.registers[1..4], &[5, 4, 5]);
+
+        let mut vm = VM::with_config(Config { overflow: Overflow::Checked, ..Config::default() });
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(i32::MIN as i64), Register::RA),
+                    Instruction::Unary(UnaryType::ABS, Register::RA, Register::RB),
+                    Instruction::HLT];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::Overflow);
+    }
+    #[test]
+    fn logical_shift() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-8), Register::RA),
+                    Instruction::Assignment(AssignmentType::STR(28), Register::RB),
+                    Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LOGICAL), Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                    Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::RIGHT), Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RD),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RC.index()], 15);
+        assert_eq!(vm.registers[Register::RD.index()], -1);
+    }
+    #[test]
+    fn compare() {
+        let kinds = [ComparisonType::EQ, ComparisonType::NE, ComparisonType::LT, ComparisonType::LE, ComparisonType::GT, ComparisonType::GE,
+                     ComparisonType::LTU, ComparisonType::LEU, ComparisonType::GTU, ComparisonType::GEU];
+        let cases = [(1, 1, [1, 0, 0, 1, 0, 1, 0, 1, 0, 1]),
+                     (-1, 2, [0, 1, 1, 1, 0, 0, 0, 0, 1, 1]),
+                     (3, -4, [0, 1, 0, 0, 1, 1, 1, 1, 0, 0])];
+        for &(a, b, expected) in cases.iter() {
+            for (kind, &expected) in kinds.iter().zip(expected.iter()) {
+                let mut vm = VM::new();
+                let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                            Instruction::Assignment(AssignmentType::STR(a), Register::RA),
+                            Instruction::Assignment(AssignmentType::STR(b), Register::RB),
+                            Instruction::Compare(*kind, Register::RA, Register::RB, Register::RC),
+                            Instruction::HLT];
+                vm.run(program).unwrap();
+                assert_eq!(vm.registers[Register::RC.index()], expected, "{:?} {} {}", kind, a, b);
+            }
+        }
+    }
+    #[test]
+    fn compare_branch() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(5), Register::RB),
+                    Instruction::Assignment(AssignmentType::STR(1), Register::RC),
+                    Instruction::Control(ControlType::LBL("loop".to_string())), 
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RC), Register::RA),
+                    Instruction::Branch(BranchType::COMPARE(ComparisonType::LT, Register::RA, Register::RB), "loop".to_string()),
+                    Instruction::Branch(BranchType::COMPARE(ComparisonType::GTU, Register::RA, Register::RB), "loop".to_string()),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RA.index()], 5);
+    }
+    #[test]
+    fn immediate() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(3), Register::RA),
+                    Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LEFT), Operand::Imm(1), Operand::Reg(Register::RA), Register::RB),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(&vm.registers[..2], &[3, 8]);
+    }
+    #[test]
+    fn registers() {
+        let r31 = Register::from_index(31).unwrap();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(7), r31),
+                    Instruction::Assignment(AssignmentType::CPY(r31), Register::RA),
+                    Instruction::HLT];
+        let mut vm = VM::with_config(Config { registers: 32, ..Config::default() });
+        vm.run(program.clone()).unwrap();
+        assert_eq!(vm.registers[0], 7);
+        assert_eq!(vm.registers.len(), 32);
+
+        let error = VM::new().run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::InvalidRegister(r31));
+        assert_eq!(error.ip, 1);
+        assert_eq!(error.to_string(), "register `r31` is out of range at ip 1 (str 7 r31)\nRegisters: [0, 0, 0, 0, 0, 0]");
+
+        let mut vm = VM::with_config(Config { float_registers: MAX_REGISTERS + 1, ..Config::default() });
+        assert_eq!(vm.run(vec![Instruction::HLT]).unwrap_err().kind, VMErrorKind::TooManyRegisters(MAX_REGISTERS + 1));
+        assert_eq!(vm.extend(vec![Instruction::HLT]).unwrap_err().kind, VMErrorKind::TooManyRegisters(MAX_REGISTERS + 1));
+
+        // Nothing is allocated until a program is loaded.
+        let mut vm = VM::with_config(Config { registers: usize::MAX, ..Config::default() });
+        assert_eq!(vm.run(vec![Instruction::HLT]).unwrap_err().kind, VMErrorKind::TooManyRegisters(usize::MAX));
+        let mut vm = VM::with_config(Config { memory_size: usize::MAX, ..Config::default() });
+        assert_eq!(vm.run(vec![Instruction::HLT]).unwrap_err().kind, VMErrorKind::MemoryTooLarge(usize::MAX));
+        assert!(vm.memory().is_empty());
+    }
+    #[test]
+    fn float() {
+        let mut vm = VM::new();
+        let output = BufferOutput::new();
+        vm.set_output(output.clone());
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::FloatAssignment(FloatAssignmentType::STR(2.0), FloatRegister::FA),
+                    Instruction::FloatUnary(FloatUnaryType::SQRT, FloatRegister::FA, FloatRegister::FB),
+                    Instruction::FloatArithmatic(FloatArithmaticType::MUL, FloatRegister::FB, FloatRegister::FB, FloatRegister::FC),
+                    Instruction::FloatIO(FloatIOType::OUT, FloatRegister::FC),
+                    Instruction::FloatArithmatic(FloatArithmaticType::DIV, FloatRegister::FA, FloatRegister::FD, FloatRegister::FD),
+                    Instruction::FloatIO(FloatIOType::OUT, FloatRegister::FD),
+                    Instruction::FloatUnary(FloatUnaryType::NEG, FloatRegister::FA, FloatRegister::FE),
+                    Instruction::FloatUnary(FloatUnaryType::SQRT, FloatRegister::FE, FloatRegister::FE),
+                    Instruction::FloatIO(FloatIOType::OUT, FloatRegister::FE),
+                    Instruction::Assignment(AssignmentType::STR(-7), Register::RA),
+                    Instruction::Conversion(ConversionType::TOFLOAT, Register::RA, FloatRegister::FF),
+                    Instruction::FloatArithmatic(FloatArithmaticType::DIV, FloatRegister::FF, FloatRegister::FA, FloatRegister::FF),
+                    Instruction::FloatIO(FloatIOType::OUTN, FloatRegister::FF),
+                    Instruction::Conversion(ConversionType::TOINT, Register::RB, FloatRegister::FF),
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RC as usize], 2);
+        vm.run(program).unwrap();
+        assert_eq!(output.contents(), "2.0000000000000004\ninf\nNaN\n-3.5");
+        assert_eq!(vm.registers[Register::RB.index()], -3);
+    }
+    #[test]
+    fn float_to_int() {
+        let cases = [(1e10, 1410065408, i32::MAX as i64, Err(VMErrorKind::Overflow)),
+                    (-1e10, -1410065408, i32::MIN as i64, Err(VMErrorKind::Overflow)),
+                    (-2147483648.9, i32::MIN as i64, i32::MIN as i64, Ok(i32::MIN as i64)),
+                    (1e300, 0, i32::MAX as i64, Err(VMErrorKind::Overflow)),
+                    (f64::INFINITY, 0, i32::MAX as i64, Err(VMErrorKind::Overflow)),
+                    (f64::NAN, 0, 0, Err(VMErrorKind::Overflow))];
+        for &(value, wrapped, saturated, ref checked) in cases.iter() {
+            for &overflow in [Overflow::Wrapping, Overflow::Saturating, Overflow::Checked].iter() {
+                let mut vm = VM::with_config(Config { overflow, ..Config::default() });
+                let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                            Instruction::FloatAssignment(FloatAssignmentType::STR(value), FloatRegister::FA),
+                            Instruction::Conversion(ConversionType::TOINT, Register::RA, FloatRegister::FA),
+                            Instruction::HLT];
+                let result = vm.run(program).map(|_| vm.registers[Register::RA.index()]).map_err(|error| error.kind);
+                match overflow {
+                    Overflow::Wrapping => assert_eq!(result, Ok(wrapped)),
+                    Overflow::Saturating => assert_eq!(result, Ok(saturated)),
+                    Overflow::Checked => assert_eq!(&result, checked),
+                }
+            }
+        }
+    }
+    #[test]
+    fn float_registers() {
+        let f8 = FloatRegister::from_index(8).unwrap();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::FloatIO(FloatIOType::OUT, f8),
+                    Instruction::HLT];
+        assert_eq!(VM::new().run(program).unwrap_err().kind, VMErrorKind::InvalidFloatRegister(f8));
     }
     #[test]
-    #[should_panic(expected = "VMError: ZeroDivision on ip 4")]
     fn zerodivision() {
         let mut vm = VM::new();
                     let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(0), Register::RB),
-                    Instruction::Arithmatic(ArithmaticType::DIV, Register::RA, Register::RB, Register::RC),
+                    Instruction::Arithmatic(ArithmaticType::DIV, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::ZeroDivision);
+        assert_eq!(error.ip, 3);
+        assert_eq!(error.instruction, Some(Instruction::Arithmatic(ArithmaticType::DIV, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC)));
+        assert_eq!(error.registers, [10, 0, 0, 0, 0, 0]);
+    }
+    #[test]
+    fn missing_main() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::MissingMainLabel);
+        assert_eq!(error.instruction, None);
+    }
+    #[test]
+    fn missing_hlt() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), Instruction::NOP];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::MissingExitInstruction);
+    }
+    #[test]
+    fn undefined_label() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "nowhere".to_string()),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::UndefinedLabel("nowhere".to_string()));
+        assert_eq!(error.ip, 1);
+
+        let mut vm = VM::new();
+        let output = BufferOutput::new();
+        vm.set_output(output.clone());
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::IO(IOType::OUT, Register::RA),
+                    Instruction::HLT,
+                    Instruction::Branch(BranchType::ZERO(Register::RA), "nowhere".to_string())];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::UndefinedLabel("nowhere".to_string()), 3));
+        assert_eq!(output.contents(), "");
+    }
+    #[test]
+    fn duplicate_label() {
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Control(ControlType::LBL("end".to_string())),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "end".to_string()),
+                    Instruction::Control(ControlType::LBL("end".to_string())),
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RC as usize], 2);
+        let error = VM::new().run(program).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::DuplicateLabel("end".to_string()), 3));
+
+        let mut vm = VM::new();
+        vm.extend(vec![Instruction::Control(ControlType::LBL("loop".to_string()))]).unwrap();
+        let error = vm.extend(vec![Instruction::NOP, Instruction::Control(ControlType::LBL("loop".to_string()))]).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::DuplicateLabel("loop".to_string()), 2));
+        assert_eq!(vm.program().len(), 1);
+    }
+    #[test]
+    fn failed_load() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Assignment(AssignmentType::STR(1), Register::RB),
+                    Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Assignment(AssignmentType::STR(2), Register::RA),
+                    Instruction::HLT];
+        vm.load(program.clone()).unwrap();
+        vm.step().unwrap();
+        let broken = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "nowhere".to_string()),
+                    Instruction::HLT];
+        let misplaced = vec![Instruction::Control(ControlType::DATA(4096, "x".to_string())),
+                    Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::HLT];
+        for (bad, ip) in [(broken, 1), (misplaced, 0)] {
+            let error = vm.load(bad).unwrap_err();
+            assert_eq!(error.ip, ip);
+            assert!(!vm.is_running());
+            assert_eq!((vm.program(), vm.ip(), vm.label("main")), (&program[..], 2, Some(1)));
+            assert_eq!(vm.step(), Ok(StepResult::Halted));
+        }
     }
 
     #[test]
@@ -284,11 +1186,11 @@ mod tests {
                     Instruction::Assignment(AssignmentType::STR(10), Register::RA),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
                     Instruction::Branch(BranchType::UNCONDITIONAL, "test".to_string()),
-                    Instruction::Arithmatic(ArithmaticType::ADD, Register::RA, Register::RB, Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RB),
                     Instruction::Control(ControlType::LBL("test".to_string())), 
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RB as usize], 5);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RB.index()], 5);
     }
     #[test]
     fn jz() {
@@ -298,11 +1200,11 @@ mod tests {
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RC),
                     Instruction::Branch(BranchType::ZERO(Register::RA), "test".to_string()),
-                    Instruction::Arithmatic(ArithmaticType::ADD, Register::RA, Register::RB, Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RB),
                     Instruction::Control(ControlType::LBL("test".to_string())), 
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RB as usize], 5);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RB.index()], 5);
     }
     #[test]
     fn jnz() {
@@ -312,11 +1214,510 @@ mod tests {
                     Instruction::Assignment(AssignmentType::STR(5), Register::RB),
                     Instruction::Assignment(AssignmentType::STR(5), Register::RC),
                     Instruction::Branch(BranchType::NOTZERO(Register::RB), "test".to_string()),
-                    Instruction::Arithmatic(ArithmaticType::ADD, Register::RA, Register::RB, Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RB),
                     Instruction::Control(ControlType::LBL("test".to_string())), 
                     Instruction::HLT];
-        vm.run(program);
-        assert_eq!( vm.registers[Register::RB as usize], 5);
+        vm.run(program).unwrap();
+        assert_eq!( vm.registers[Register::RB.index()], 5);
     }
-}
+    #[test]
+    fn load_store() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(10), Register::RA),
+                    Instruction::Assignment(AssignmentType::STR(42), Register::RB),
+                    Instruction::Memory(MemoryType::STORE, Register::RB, Address { base: Register::RA, offset: 5 }),
+                    Instruction::Assignment(AssignmentType::STR(20), Register::RA),
+                    Instruction::Memory(MemoryType::LOAD, Register::RC, Address { base: Register::RA, offset: -5 }),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.memory[15], 42);
+        assert_eq!(vm.registers[Register::RC.index()], 42);
+    }
+    #[test]
+    fn segfault() {
+        let mut vm = VM::with_config(Config { memory_size: 16, ..Config::default() });
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(15), Register::RA),
+                    Instruction::Memory(MemoryType::STORE, Register::RB, Address { base: Register::RA, offset: 0 }),
+                    Instruction::Memory(MemoryType::LOAD, Register::RC, Address { base: Register::RA, offset: 1 }),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::SegFault(16));
+        assert_eq!(error.ip, 3);
+
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Memory(MemoryType::LOAD, Register::RC, Address { base: Register::RA, offset: -1 }),
+                    Instruction::HLT];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::SegFault(-1));
+    }
+    #[test]
+    fn push_pop() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(1), Register::RA),
+                    Instruction::Assignment(AssignmentType::STR(2), Register::RB),
+                    Instruction::Stack(StackType::PUSH, Register::RA),
+                    Instruction::Stack(StackType::PUSH, Register::RB),
+                    Instruction::Stack(StackType::POP, Register::RA),
+                    Instruction::Stack(StackType::POP, Register::RB),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RA.index()], 2);
+        assert_eq!(vm.registers[Register::RB.index()], 1);
+        assert!(vm.stack.is_empty());
+    }
+    #[test]
+    fn call_ret() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(5), Register::RA),
+                    Instruction::Branch(BranchType::CALL, "double".to_string()),
+                    Instruction::Branch(BranchType::CALL, "double".to_string()),
+                    Instruction::HLT,
+                    Instruction::Control(ControlType::LBL("double".to_string())), 
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RA), Register::RA),
+                    Instruction::RET];
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RA.index()], 20);
+        assert!(vm.stack.is_empty());
+    }
+    #[test]
+    fn step() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Assignment(AssignmentType::STR(7), Register::RB),
+                    Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Assignment(AssignmentType::STR(5), Register::RA),
+                    Instruction::Branch(BranchType::CALL, "double".to_string()),
+                    Instruction::HLT,
+                    Instruction::Control(ControlType::LBL("double".to_string())),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Reg(Register::RA), Register::RA),
+                    Instruction::RET];
+        vm.load(program).unwrap();
+        assert_eq!((vm.ip(), vm.label("double")), (1, Some(5)));
+        let mut ips = vec![];
+        while vm.step().unwrap() == StepResult::Running {
+            ips.push(vm.ip());
+        }
+        assert_eq!(ips, vec![2, 3, 6, 7, 4]);
+        assert_eq!(vm.registers(), &[10, 0, 0, 0, 0, 0]);
+        assert_eq!(vm.step(), Ok(StepResult::Halted));
 
+        vm.set_register(Register::RC, 1 << 40).unwrap();
+        assert_eq!(vm.registers()[Register::RC.index()], 0);
+        assert_eq!(vm.set_register(Register::from_index(6).unwrap(), 1).unwrap_err().kind, VMErrorKind::InvalidRegister(Register::from_index(6).unwrap()));
+    }
+    #[test]
+    fn extend() {
+        let mut vm = VM::new();
+        let counter = vec![Instruction::Assignment(AssignmentType::STR(3), Register::RA),
+                    Instruction::Control(ControlType::LBL("loop".to_string())),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RB), Operand::Imm(1), Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::SUB, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+                    Instruction::Branch(BranchType::NOTZERO(Register::RA), "loop".to_string())];
+        vm.extend(counter).unwrap();
+        assert_eq!((vm.ip(), vm.is_running()), (0, true));
+        while vm.ip() < vm.program().len() {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.registers(), &[0, 3, 0, 0, 0, 0]);
+
+        // A later block can branch back into an earlier one.
+        vm.extend(vec![Instruction::Assignment(AssignmentType::STR(2), Register::RA),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "loop".to_string())]).unwrap();
+        assert_eq!(vm.ip(), 5);
+        assert_eq!(vm.run_for(8), Ok(StepResult::Running));
+        assert_eq!((vm.ip(), vm.registers()), (5, &[0, 5, 0, 0, 0, 0][..]));
+
+        // A block that can't be loaded leaves the program as it was.
+        let error = vm.extend(vec![Instruction::NOP, Instruction::Branch(BranchType::UNCONDITIONAL, "nowhere".to_string())]).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::UndefinedLabel("nowhere".to_string()), 8));
+        assert_eq!((vm.ip(), vm.program().len()), (5, 7));
+
+        // Nor is any of its `.data` written if one directive doesn't fit.
+        let mut vm = VM::with_config(Config { memory_size: 8, ..Config::default() });
+        let error = vm.extend(vec![Instruction::Control(ControlType::DATA(0, "ab".to_string())),
+                    Instruction::Control(ControlType::DATA(6, "xyz".to_string()))]).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::SegFault(8), 1));
+        assert_eq!(vm.memory(), &[0; 8]);
+        assert!(vm.program().is_empty());
+        vm.extend(vec![Instruction::Control(ControlType::DATA(5, "xy".to_string()))]).unwrap();
+        assert_eq!(vm.memory(), &[0, 0, 0, 0, 0, 120, 121, 0]);
+
+        vm.extend(vec![Instruction::HLT]).unwrap();
+        assert_eq!(vm.step(), Ok(StepResult::Halted));
+        assert!(!vm.is_running());
+        vm.extend(vec![Instruction::IO(IOType::OUT, Register::RB)]).unwrap();
+        assert!(vm.is_running());
+
+        // Each block gets its own fuel.
+        let mut vm = VM::with_config(Config { fuel: Some(3), ..Config::default() });
+        vm.extend(vec![Instruction::NOP, Instruction::NOP]).unwrap();
+        assert_eq!(vm.run_for(2), Ok(StepResult::Running));
+        vm.extend(vec![Instruction::NOP, Instruction::NOP, Instruction::NOP, Instruction::NOP]).unwrap();
+        assert_eq!(vm.run_for(3), Ok(StepResult::Running));
+        assert_eq!(vm.step().unwrap_err().kind, VMErrorKind::OutOfFuel);
+    }
+    #[test]
+    fn run_for() {
+        let mut vm = VM::new();
+        assert!(!vm.is_running());
+        assert_eq!(vm.run_for(1), Ok(StepResult::Halted));
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+                    Instruction::Branch(BranchType::COMPARE(ComparisonType::LT, Register::RA, Register::RB), "main".to_string()),
+                    Instruction::Arithmatic(ArithmaticType::DIV, Operand::Reg(Register::RA), Operand::Reg(Register::RC), Register::RA),
+                    Instruction::HLT];
+        vm.load(program.clone()).unwrap();
+        vm.set_register(Register::RB, 10).unwrap();
+        assert_eq!(vm.run_for(7), Ok(StepResult::Running));
+        assert_eq!((vm.ip(), vm.registers()[Register::RA.index()]), (1, 3));
+        assert_eq!(vm.run_for(100).unwrap_err().kind, VMErrorKind::ZeroDivision);
+        assert!(!vm.is_running());
+        assert_eq!((vm.ip(), vm.registers()[Register::RA.index()]), (3, 10));
+
+        vm.load(program).unwrap();
+        vm.set_register(Register::RB, 10).unwrap();
+        vm.set_register(Register::RC, 2).unwrap();
+        assert!(vm.is_running());
+        assert_eq!(vm.run_for(100), Ok(StepResult::Halted));
+        assert_eq!(vm.registers()[Register::RA.index()], 5);
+        assert!(!vm.is_running());
+    }
+    #[test]
+    fn fuel() {
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "main".to_string()),
+                    Instruction::HLT];
+        let mut vm = VM::with_config(Config { fuel: Some(100), ..Config::default() });
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::OutOfFuel);
+        assert_eq!((error.ip, error.instruction), (1, Some(Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA))));
+        assert_eq!((vm.steps(), vm.registers()[Register::RA.index()]), (100, 50));
+
+        // Labels passed through on the way don't use up fuel.
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+                    Instruction::Control(ControlType::LBL("again".to_string())),
+                    Instruction::Control(ControlType::DATA(0, "".to_string())),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "main".to_string()),
+                    Instruction::HLT];
+        let mut vm = VM::with_config(Config { fuel: Some(100), ..Config::default() });
+        let error = vm.run(program).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VMErrorKind::OutOfFuel, 1));
+        assert_eq!((vm.steps(), vm.registers()[Register::RA.index()]), (100, 50));
+    }
+    #[test]
+    fn timeout() {
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Branch(BranchType::UNCONDITIONAL, "main".to_string()),
+                    Instruction::HLT];
+        let mut vm = VM::with_config(Config { timeout: Some(Duration::from_millis(0)), ..Config::default() });
+        let error = vm.run(program.clone()).unwrap_err();
+        assert_eq!((error.kind, error.ip, vm.steps()), (VMErrorKind::Timeout, 1, 0));
+
+        let mut vm = VM::with_config(Config { timeout: Some(Duration::from_millis(20)), ..Config::default() });
+        assert_eq!(vm.run(program.clone()).unwrap_err().kind, VMErrorKind::Timeout);
+        assert!(vm.steps().is_multiple_of(TIMEOUT_INTERVAL));
+
+        // A timeout too long to represent is no limit at all.
+        let mut vm = VM::with_config(Config { timeout: Some(Duration::MAX), ..Config::default() });
+        vm.load(program).unwrap();
+        assert_eq!(vm.run_for(3 * TIMEOUT_INTERVAL as usize), Ok(StepResult::Running));
+        vm.extend(vec![Instruction::NOP]).unwrap();
+        assert_eq!(vm.run_for(1), Ok(StepResult::Running));
+    }
+    #[test]
+    fn stack_overflow() {
+        let mut vm = VM::with_config(Config { stack_size: 4, ..Config::default() });
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Branch(BranchType::CALL, "main".to_string()),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::StackOverflow);
+        assert_eq!(vm.stack.len(), 4);
+
+        // A program loaded afterwards starts with an empty stack.
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Stack(StackType::PUSH, Register::RA),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.stack(), &[0]);
+    }
+    #[test]
+    fn reload() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::DATA(2, "a".to_string())),
+                    Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Assignment(AssignmentType::STR(7), Register::RA),
+                    Instruction::FloatAssignment(FloatAssignmentType::STR(1.5), FloatRegister::FA),
+                    Instruction::Memory(MemoryType::STORE, Register::RA, Address { base: Register::RB, offset: 0 }),
+                    Instruction::Stack(StackType::PUSH, Register::RA),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!((vm.memory()[0], vm.stack()), (7, &[7][..]));
+
+        // A program that can't be loaded keeps the previous state.
+        assert!(vm.load(vec![Instruction::HLT]).is_err());
+        assert_eq!((vm.registers()[0], vm.float_registers()[0], vm.memory()[0], vm.stack()), (7, 1.5, 7, &[7][..]));
+
+        vm.load(vec![Instruction::Control(ControlType::LBL("main".to_string())), Instruction::HLT]).unwrap();
+        assert_eq!((vm.registers()[0], vm.float_registers()[0], &vm.memory()[..4], vm.stack()), (0, 0.0, &[0, 0, 0, 0][..], &[][..]));
+    }
+    #[test]
+    fn stack_underflow() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Stack(StackType::POP, Register::RA),
+                    Instruction::HLT];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::StackUnderflow);
+
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::RET,
+                    Instruction::HLT];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::StackUnderflow);
+    }
+    #[test]
+    fn ip_out_of_range() {
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Branch(BranchType::ZERO(Register::RA), "end".to_string()),
+                    Instruction::HLT,
+                    Instruction::Control(ControlType::LBL("end".to_string())),
+                    Instruction::Stack(StackType::PUSH, Register::RA)];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!((error.kind, error.ip, error.instruction), (VMErrorKind::IpOutOfRange(5), 5, None));
+        assert!(!vm.is_running());
+
+        for &(address, expected) in [(-1, -1), (7, 8)].iter() {
+            let mut vm = VM::new();
+            let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                        Instruction::Assignment(AssignmentType::STR(address), Register::RA),
+                        Instruction::Stack(StackType::PUSH, Register::RA),
+                        Instruction::RET,
+                        Instruction::HLT];
+            assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::IpOutOfRange(expected));
+        }
+    }
+    #[test]
+    fn input() {
+        let mut vm = VM::new();
+        vm.set_input(ScriptedInput::new(vec![6, 7]));
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::IO(IOType::IN, Register::RA),
+                    Instruction::IO(IOType::IN, Register::RB),
+                    Instruction::Arithmatic(ArithmaticType::MUL, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RC.index()], 42);
+    }
+    #[test]
+    fn end_of_input() {
+        let mut vm = VM::new();
+        vm.set_input(ScriptedInput::new(vec![1]));
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::IO(IOType::IN, Register::RA),
+                    Instruction::IO(IOType::IN, Register::RB),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::Input(InputError::EndOfInput));
+        assert_eq!(error.ip, 2);
+        assert_eq!(error.registers[Register::RA.index()], 1);
+    }
+    #[test]
+    fn input_out_of_range() {
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::IO(IOType::IN, Register::RA),
+                    Instruction::HLT];
+        for overflow in [Overflow::Wrapping, Overflow::Checked, Overflow::Saturating] {
+            let mut vm = VM::with_config(Config { overflow, ..Config::default() });
+            vm.set_input(ScriptedInput::new(vec![99999999999]));
+            let error = vm.run(program.clone()).unwrap_err();
+            assert_eq!((error.kind, error.ip), (VMErrorKind::Input(InputError::OutOfRange(99999999999)), 1));
+        }
+        let mut vm = VM::with_config(Config { word_size: WordSize::Bits64, ..Config::default() });
+        vm.set_input(ScriptedInput::new(vec![99999999999]));
+        vm.run(program).unwrap();
+        assert_eq!(vm.registers[Register::RA.index()], 99999999999);
+    }
+    #[test]
+    fn output() {
+        let mut vm = VM::new();
+        let output = BufferOutput::new();
+        vm.set_output(output.clone());
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-3), Register::RA),
+                    Instruction::IO(IOType::OUT, Register::RA),
+                    Instruction::IO(IOType::OUT, Register::RB),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(output.contents(), "-3\n0\n");
+    }
+    #[test]
+    fn text_output() {
+        let mut vm = VM::new();
+        let output = BufferOutput::new();
+        vm.set_output(output.clone());
+        let program = vec![Instruction::Control(ControlType::DATA(16, "hé\n".to_string())),
+                    Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-3), Register::RA),
+                    Instruction::Assignment(AssignmentType::STR(33), Register::RB),
+                    Instruction::Assignment(AssignmentType::STR(16), Register::RC),
+                    Instruction::IO(IOType::OUTN, Register::RA),
+                    Instruction::IO(IOType::OUTC, Register::RB),
+                    Instruction::IO(IOType::PRINTS, Register::RC),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(output.contents(), "-3!hé\n");
+        assert_eq!(&vm.memory[16..20], &[104, 233, 10, 0]);
+    }
+    #[test]
+    fn send() {
+        let mut vm = VM::new();
+        let output = BufferOutput::new();
+        vm.set_output(output.clone());
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                    Instruction::Assignment(AssignmentType::STR(7), Register::RA),
+                    Instruction::IO(IOType::OUT, Register::RA),
+                    Instruction::HLT];
+        thread::spawn(move || vm.run(program)).join().unwrap().unwrap();
+        assert_eq!(output.contents(), "7\n");
+    }
+    #[test]
+    fn invalid_character() {
+        let mut vm = VM::new();
+        vm.set_output(BufferOutput::new());
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-1), Register::RA),
+                    Instruction::IO(IOType::OUTC, Register::RA),
+                    Instruction::HLT];
+        assert_eq!(vm.run(program).unwrap_err().kind, VMErrorKind::InvalidCharacter(-1));
+    }
+    #[test]
+    fn data_out_of_bounds() {
+        let mut vm = VM::with_config(Config { memory_size: 4, ..Config::default() });
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Control(ControlType::DATA(1, "abc".to_string())),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::SegFault(4));
+        assert_eq!(error.ip, 1);
+    }
+    #[test]
+    fn overflow() {
+        let cases = [
+            (WordSize::Bits32, ArithmaticType::ADD, i32::MAX as i64, 1, i32::MIN as i64, i32::MAX as i64),
+            (WordSize::Bits32, ArithmaticType::SUB, i32::MIN as i64, 1, i32::MAX as i64, i32::MIN as i64),
+            (WordSize::Bits32, ArithmaticType::MUL, i32::MAX as i64, 2, -2, i32::MAX as i64),
+            (WordSize::Bits32, ArithmaticType::DIV, i32::MIN as i64, -1, i32::MIN as i64, i32::MAX as i64),
+            (WordSize::Bits64, ArithmaticType::ADD, i64::MAX, 1, i64::MIN, i64::MAX),
+            (WordSize::Bits64, ArithmaticType::MUL, i64::MAX, 2, -2, i64::MAX),
+            (WordSize::Bits64, ArithmaticType::DIV, i64::MIN, -1, i64::MIN, i64::MAX),
+        ];
+        for &(word_size, ref kind, a, b, wrapped, saturated) in cases.iter() {
+            for &(overflow, ref expected) in [(Overflow::Wrapping, Ok(wrapped)), (Overflow::Checked, Err(VMErrorKind::Overflow)), (Overflow::Saturating, Ok(saturated))].iter() {
+                let mut vm = VM::with_config(Config { overflow, word_size, ..Config::default() });
+                let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                            Instruction::Assignment(AssignmentType::STR(a), Register::RA),
+                            Instruction::Assignment(AssignmentType::STR(b), Register::RB),
+                            Instruction::Arithmatic(*kind, Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                            Instruction::HLT];
+                let result = vm.run(program).map(|_| vm.registers[Register::RC.index()]).map_err(|error| error.kind);
+                assert_eq!(&result, expected);
+            }
+        }
+    }
+    #[test]
+    fn word_size() {
+        let factorial = |word_size| {
+            let mut vm = VM::with_config(Config { word_size, ..Config::default() });
+            let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                        Instruction::Assignment(AssignmentType::STR(20), Register::RA),
+                        Instruction::Assignment(AssignmentType::STR(1), Register::RB),
+                        Instruction::Control(ControlType::LBL("loop".to_string())),
+                        Instruction::Arithmatic(ArithmaticType::MUL, Operand::Reg(Register::RB), Operand::Reg(Register::RA), Register::RB),
+                        Instruction::Arithmatic(ArithmaticType::SUB, Operand::Reg(Register::RA), Operand::Imm(1), Register::RA),
+                        Instruction::Branch(BranchType::NOTZERO(Register::RA), "loop".to_string()),
+                        Instruction::HLT];
+            vm.run(program).unwrap();
+            vm.registers[Register::RB.index()]
+        };
+        assert_eq!(factorial(WordSize::Bits64), 2432902008176640000);
+        assert_eq!(factorial(WordSize::Bits32), -2102132736);
+
+        let mut vm = VM::with_config(Config { word_size: WordSize::Bits64, ..Config::default() });
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(-1), Register::RA),
+                    Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LOGICAL), Operand::Reg(Register::RA), Operand::Imm(32), Register::RB),
+                    Instruction::Compare(ComparisonType::GTU, Register::RA, Register::RB, Register::RC),
+                    Instruction::Assignment(AssignmentType::STR(0xffffffff), Register::RD),
+                    Instruction::HLT];
+        vm.run(program).unwrap();
+        assert_eq!(&vm.registers[1..4], &[0xffffffff, 1, 0xffffffff]);
+
+        let mut vm = VM::new();
+        let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                    Instruction::Assignment(AssignmentType::STR(0xffffffff), Register::RA),
+                    Instruction::Arithmatic(ArithmaticType::ADD, Operand::Reg(Register::RA), Operand::Imm(5000000000), Register::RA),
+                    Instruction::HLT];
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::LiteralOutOfRange(5000000000));
+        assert_eq!(error.ip, 2);
+        assert_eq!(vm.registers[Register::RA.index()], 0);
+    }
+    #[test]
+    fn shift_out_of_range() {
+        let cases = [
+            (ShiftType::LEFT, 1, 33, 2, 0),
+            (ShiftType::RIGHT, -8, 32, -8, -1),
+            (ShiftType::RIGHT, 8, -1, 0, 0),
+        ];
+        for &(ref kind, a, b, wrapped, saturated) in cases.iter() {
+            for &(overflow, ref expected) in [(Overflow::Wrapping, Ok(wrapped)), (Overflow::Checked, Err(VMErrorKind::Overflow)), (Overflow::Saturating, Ok(saturated))].iter() {
+                let mut vm = VM::with_config(Config { overflow, ..Config::default() });
+                let program = vec![Instruction::Control(ControlType::LBL("main".to_string())), 
+                            Instruction::Assignment(AssignmentType::STR(a), Register::RA),
+                            Instruction::Assignment(AssignmentType::STR(b), Register::RB),
+                            Instruction::Bitwise(BitwiseType::SHIFT(*kind), Operand::Reg(Register::RA), Operand::Reg(Register::RB), Register::RC),
+                            Instruction::HLT];
+                let result = vm.run(program).map(|_| vm.registers[Register::RC.index()]).map_err(|error| error.kind);
+                assert_eq!(&result, expected);
+            }
+        }
+    }
+    #[test]
+    fn shift_overflow() {
+        let cases = [
+            (3, 31, i32::MIN as i64, i32::MAX as i64),
+            (1, 31, i32::MIN as i64, i32::MAX as i64),
+            (-3, 31, i32::MIN as i64, i32::MIN as i64),
+            (0x4000_0000, 2, 0, i32::MAX as i64),
+        ];
+        for &(a, b, wrapped, saturated) in cases.iter() {
+            for &(overflow, ref expected) in [(Overflow::Wrapping, Ok(wrapped)), (Overflow::Checked, Err(VMErrorKind::Overflow)), (Overflow::Saturating, Ok(saturated))].iter() {
+                let mut vm = VM::with_config(Config { overflow, ..Config::default() });
+                let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                            Instruction::Assignment(AssignmentType::STR(a), Register::RA),
+                            Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LEFT), Operand::Reg(Register::RA), Operand::Imm(b), Register::RB),
+                            Instruction::HLT];
+                let result = vm.run(program).map(|_| vm.registers[Register::RB.index()]).map_err(|error| error.kind);
+                assert_eq!(&result, expected);
+            }
+        }
+
+        // Shifts that keep every bit succeed in every mode, including into the sign bit of a negative value.
+        for &overflow in [Overflow::Wrapping, Overflow::Checked, Overflow::Saturating].iter() {
+            let mut vm = VM::with_config(Config { overflow, ..Config::default() });
+            let program = vec![Instruction::Control(ControlType::LBL("main".to_string())),
+                        Instruction::Assignment(AssignmentType::STR(-1), Register::RA),
+                        Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LEFT), Operand::Reg(Register::RA), Operand::Imm(31), Register::RB),
+                        Instruction::Bitwise(BitwiseType::SHIFT(ShiftType::LEFT), Operand::Imm(3), Operand::Imm(29), Register::RC),
+                        Instruction::HLT];
+            vm.run(program).unwrap();
+            assert_eq!(&vm.registers[1..3], &[i32::MIN as i64, 0x6000_0000]);
+        }
+    }
+}